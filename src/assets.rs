@@ -7,6 +7,8 @@ use std::path::Path;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use rand::prelude::IndexedRandom;
 
+use crate::components::ResourceNode;
+
 #[derive(Deserialize,Debug)]
 pub struct SeiSprite {
     pub name: String,
@@ -143,6 +145,9 @@ fn load_assets(
                         scale: Vec3::splat(2.0),
                         ..default()
                     },
+                    ResourceNode {
+                        amount: rand::random_range(100..500),
+                    },
                 ));
             }
         }
@@ -1,2 +1,2 @@
 pub mod unit;
-pub use unit::UnitBundle;
+pub use unit::{UnitBundle, UnitSpawn};
@@ -1,11 +1,38 @@
 use crate::components::*;
+use crate::devices::{Identity, UnitIO};
 use crate::tools::assembler::Program;
+use crate::unit_repo::UnitStats;
 use bevy::prelude::*;
 
 #[derive(Component)]
 pub struct Unit {}
 
-const UNIT_SIZE: Vec3 = Vec3::new(2., 2., 1.);
+/// Collider width of a unit at scale 1
+const COLLIDER_WIDTH_PER_SCALE: f32 = 25.;
+
+/// Who a new unit is and where it shows up
+pub struct UnitSpawn {
+    pub instance_id: InstanceId,
+    pub instance_key: InstanceKey,
+    pub team: u8,
+    pub pos: Vec2,
+    /// Tick the unit first came into the world at
+    pub spawn_tick: u64,
+    /// Saved version of the code the unit runs, None for unsaved code
+    pub version_id: Option<u64>,
+}
+
+/// What the Identity device reports for a unit, health is kept in sync afterwards
+fn unit_identity(unit_type_id: u64, stats: &UnitStats, spawn: &UnitSpawn) -> Identity {
+    Identity {
+        instance_id: spawn.instance_id.0,
+        unit_type: unit_type_id as u16,
+        team: spawn.team,
+        spawn_tick: spawn.spawn_tick,
+        hp: stats.max_hp,
+        max_hp: stats.max_hp,
+    }
+}
 
 #[derive(Bundle)]
 pub struct UnitBundle {
@@ -15,26 +42,68 @@ pub struct UnitBundle {
     transform: Transform,
     selectable: Selectable,
     collider: Collider,
+    team: Team,
+    instance_id: InstanceId,
+    instance_key: InstanceKey,
+    health: Health,
+    inventory: Inventory,
+    max_speed: MaxSpeed,
 }
 
 impl UnitBundle {
     pub fn new(
         unit_type_id: u64,
-        pos: Vec2,
-        sprite: Sprite,
         program: &Program,
+        sprite: Sprite,
+        stats: &UnitStats,
+        spawn: UnitSpawn,
+        world_seed: u64,
     ) -> Self {
+        let mut device = UnitIO::with_devices(stats.devices);
+        // Seeded before the reset vector runs, so init code gets its own stream too
+        device.random.reseed(world_seed, spawn.instance_id.0 as u64);
+        device.identity = unit_identity(unit_type_id, stats, &spawn);
+        let executable = Executable::with_io(unit_type_id, program, device);
+
+        UnitBundle::with_executable(executable, sprite, stats, spawn)
+    }
+
+    /// Unit running an executable that's already set up, e.g. one restored from a save
+    pub fn with_executable(
+        mut executable: Executable,
+        sprite: Sprite,
+        stats: &UnitStats,
+        spawn: UnitSpawn,
+    ) -> Self {
+        executable.device.identity = unit_identity(executable.unit_type_id, stats, &spawn);
+        let UnitSpawn {
+            instance_id,
+            instance_key,
+            team,
+            pos,
+            version_id,
+            ..
+        } = spawn;
+        executable.version_id = version_id;
+        executable.limits.num_cycles = stats.cycle_budget;
+
         UnitBundle {
             unit: Unit {},
             sprite,
-            executable: Executable::from_program(unit_type_id, program),
+            executable,
             transform: Transform {
                 translation: pos.extend(0.),
-                scale: UNIT_SIZE,
+                scale: Vec3::new(stats.scale, stats.scale, 1.),
                 ..default()
             },
             selectable: Selectable::new(),
-            collider: Collider::new(pos, 50.),
+            collider: Collider::new(pos, COLLIDER_WIDTH_PER_SCALE * stats.scale),
+            team: Team(team),
+            instance_id,
+            instance_key,
+            health: Health::new(stats.max_hp, stats.armor),
+            inventory: Inventory::default(),
+            max_speed: MaxSpeed(stats.max_speed),
         }
     }
 }
@@ -0,0 +1,80 @@
+//! Subcommands that work on the unit database without starting the game:
+//!
+//!     kikai-rs export <unit type> <file> [--full]
+//!     kikai-rs import <file> [--on-conflict fail|rename|merge]
+//!
+//! Both work on the workspace picked with `--db` and `--workspace`, like the game does.
+use anyhow::{anyhow, bail, Result};
+
+use crate::unit_export::{export_unit_type, import_unit_type, read_bundle, write_bundle, ConflictPolicy};
+use crate::unit_repo::UnitRepository;
+use crate::workspace::Workspaces;
+
+const USAGE: &str = "usage:
+    kikai-rs [--db <path>|:memory:] [--workspace <name>] export <unit type> <file> [--full]
+    kikai-rs [--db <path>|:memory:] [--workspace <name>] import <file> [--on-conflict fail|rename|merge]";
+
+/// Runs the subcommand in `args`, if any. Returns None when the game should start instead.
+pub fn run(args: &[String], workspaces: &Workspaces) -> Option<Result<()>> {
+    let command = args.get(1)?;
+    let rest = &args[2..];
+
+    let result = match command.as_str() {
+        "export" => export(rest, workspaces),
+        "import" => import(rest, workspaces),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => return None,
+    };
+
+    Some(result)
+}
+
+fn open_repository(workspaces: &Workspaces) -> Result<UnitRepository> {
+    Ok(workspaces.open(&workspaces.current)?)
+}
+
+fn export(args: &[String], workspaces: &Workspaces) -> Result<()> {
+    let (Some(name), Some(path)) = (args.first(), args.get(1)) else {
+        bail!(USAGE);
+    };
+    let full_history = args[2..].iter().any(|a| a == "--full");
+
+    let repo = open_repository(workspaces)?;
+    let unit_id = repo
+        .find_unit_by_name(name)?
+        .ok_or_else(|| anyhow!("no unit type named {}", name))?;
+
+    let bundle = export_unit_type(&repo, unit_id, full_history)?;
+    write_bundle(&bundle, path)?;
+    println!(
+        "Exported {} ({} versions) to {}",
+        bundle.name,
+        bundle.versions.len(),
+        path
+    );
+    Ok(())
+}
+
+fn import(args: &[String], workspaces: &Workspaces) -> Result<()> {
+    let Some(path) = args.first() else {
+        bail!(USAGE);
+    };
+
+    let policy = match args.iter().position(|a| a == "--on-conflict") {
+        Some(i) => {
+            let name = args.get(i + 1).ok_or_else(|| anyhow!(USAGE))?;
+            ConflictPolicy::parse(name).ok_or_else(|| anyhow!("unknown conflict policy {}", name))?
+        }
+        None => ConflictPolicy::Fail,
+    };
+
+    let repo = open_repository(workspaces)?;
+    let bundle = read_bundle(path)?;
+    let unit_id = import_unit_type(&repo, &bundle, policy)?;
+    let unit = repo.get_unit(unit_id)?;
+    println!("Imported {} as unit type {} ({})", bundle.name, unit_id, unit.name);
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+
+use crate::components::{Executable, Health, InstanceId};
+use crate::devices::weapon::{ShotTarget, STATUS_NO_TARGET, STATUS_OUT_OF_RANGE};
+use crate::executable::update_executables;
+use crate::spatial::SpatialIndex;
+
+/// How close to the given coordinates a unit has to be to be hit by a coordinate shot
+const COORD_TARGET_RADIUS: f32 = 20.0;
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct UnitDestroyed {
+    pub entity: Entity,
+    pub instance_id: InstanceId,
+    pub destroyed_by: Entity,
+}
+
+struct Shot {
+    shooter: Entity,
+    shooter_id: InstanceId,
+    origin: Vec2,
+    target: ShotTarget,
+    range: u16,
+    damage: u16,
+}
+
+fn resolve_combat(
+    mut commands: Commands,
+    mut query: Query<(Entity, &InstanceId, &mut Executable, &Transform, &mut Health)>,
+    mut destroyed_events: EventWriter<UnitDestroyed>,
+    index: Res<SpatialIndex>,
+) {
+    let mut shots = Vec::new();
+
+    for (entity, instance_id, mut executable, transform, _) in &mut query {
+        let weapon = &mut executable.device.weapon;
+        weapon.tick();
+
+        if let Some(target) = weapon.pending_shot.take() {
+            shots.push(Shot {
+                shooter: entity,
+                shooter_id: *instance_id,
+                origin: transform.translation.xy(),
+                target,
+                range: weapon.range,
+                damage: weapon.damage as u16,
+            });
+        }
+    }
+
+    for shot in shots {
+        let victim = match shot.target {
+            ShotTarget::Handle(handle) => query
+                .iter()
+                .find(|(_, id, ..)| id.0 == handle)
+                .map(|(entity, _, _, transform, _)| (entity, transform.translation.xy())),
+            ShotTarget::Coords(x, y) => index
+                .query_radius(Vec2::new(x as f32, y as f32), COORD_TARGET_RADIUS)
+                .into_iter()
+                .find(|(entity, _)| *entity != shot.shooter && query.contains(*entity)),
+        };
+
+        let status = match victim {
+            Some((entity, _)) if entity == shot.shooter => Some(STATUS_NO_TARGET),
+            Some((_, pos)) if pos.distance(shot.origin) > shot.range as f32 => {
+                Some(STATUS_OUT_OF_RANGE)
+            }
+            Some(_) => None,
+            None => Some(STATUS_NO_TARGET),
+        };
+
+        if let Some(status) = status {
+            if let Ok((_, _, mut executable, _, _)) = query.get_mut(shot.shooter) {
+                executable.set_weapon_status(status);
+            }
+            continue;
+        }
+
+        let (victim, _) = victim.unwrap();
+        let Ok((_, victim_id, mut executable, _, mut health)) = query.get_mut(victim) else {
+            continue;
+        };
+
+        // Already destroyed by an earlier shot this frame
+        if health.is_dead() {
+            continue;
+        }
+
+        health.take_hit(shot.damage);
+        executable.set_attacker(shot.shooter_id.0);
+
+        let hit_vec = executable.hit_vector();
+        if hit_vec != 0 {
+            executable.vector_queue.push(hit_vec);
+        }
+
+        if health.is_dead() {
+            destroyed_events.send(UnitDestroyed {
+                entity: victim,
+                instance_id: *victim_id,
+                destroyed_by: shot.shooter,
+            });
+            commands.entity(victim).despawn();
+        }
+    }
+}
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<UnitDestroyed>()
+            .add_systems(Update, resolve_combat.after(update_executables));
+    }
+}
@@ -1,14 +1,44 @@
-use crate::devices::{CommandPorts, MovementPorts, RadioPorts, UnitIO};
+use crate::devices::radio::ReceivedPacket;
+use crate::devices::{
+    CargoPorts, CommandPorts, InstalledDevices, InterfacePorts, MovementPorts, RadioPorts, RawPorts,
+    SensorPorts, StoragePorts, UnitIO, WeaponPorts,
+};
 use crate::tools::assembler::{assemble, Program};
+use crate::tools::symbols::plan_state_migration;
 use bevy::prelude::*;
-use raven_uxn::{Backend, Uxn, UxnRam};
-use std::collections::BTreeSet;
+use raven_uxn::{Backend, Uxn, UxnRam, DEV_SIZE};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 
-use crate::radio::RadioMessage;
+use crate::radio::{RadioMessage, MAX_HOPS};
 
 pub struct CpuLimits {
-    num_cycles: u32,
+    /// Cycles the unit gets to run every tick
+    pub num_cycles: u32,
+}
+
+pub const DEFAULT_CYCLE_BUDGET: u32 = 1000;
+
+/// Everything the program can see of its VM, to save it and bring it back later
+#[derive(Clone, PartialEq, Debug)]
+pub struct VmSnapshot {
+    /// All 64KiB of RAM
+    pub ram: Vec<u8>,
+    /// The whole device page
+    pub devices: Vec<u8>,
+    /// Working stack, bottom first
+    pub stack: Vec<u8>,
+    /// Return stack, bottom first
+    pub ret: Vec<u8>,
+}
+
+/// What a state preserving reload did with the new program's labels
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReloadReport {
+    /// Labels whose memory was carried over from the old program
+    pub migrated: Vec<String>,
+    /// Labels that start out fresh
+    pub reset: Vec<String>,
 }
 
 #[derive(Component)]
@@ -19,20 +49,42 @@ pub struct Executable {
     pub program: Program,
     pub breakpoints: BTreeSet<u16>,
     pub pc: Option<u16>,
-    /// Keeps a stack of vectors we need to call
+    /// Keeps a queue of vectors we need to call, oldest first
     pub vector_queue: Vec<u16>,
-    pub unit_id: u64,
+    /// Id of the unit type this program belongs to, shared by every unit of the type
+    pub unit_type_id: u64,
+    /// Saved version of the unit type's code being run, None for unsaved code
+    pub version_id: Option<u64>,
     pub cycles_left: u32,
+    /// Set when the last run stopped because it ran out of cycles, so it can be
+    /// resumed on the next frame
+    pub out_of_cycles: bool,
+    /// Hop count of the radio packet whose reception handler is running, if one is.
+    /// Messages the handler sends are one hop further along, even if it only finishes
+    /// on a later frame
+    pub handled_hops: Option<u8>,
 }
 
 impl Executable {
-    pub fn from_file(unit_id: u64, path: impl AsRef<Path>) -> Self {
+    pub fn from_file(unit_type_id: u64, path: impl AsRef<Path>) -> Self {
         let src = std::fs::read_to_string(path).unwrap();
         let program = assemble(src).unwrap();
-        Executable::from_program(unit_id, &program)
+        Executable::from_program(unit_type_id, &program)
+    }
+
+    pub fn from_program(unit_type_id: u64, program: &Program) -> Self {
+        Executable::with_devices(unit_type_id, program, InstalledDevices::ALL)
+    }
+
+    /// Builds an executable with only some devices installed, before the program's reset
+    /// vector runs so it can't use the missing ones either
+    pub fn with_devices(unit_type_id: u64, program: &Program, installed: InstalledDevices) -> Self {
+        Executable::with_io(unit_type_id, program, UnitIO::with_devices(installed))
     }
 
-    pub fn from_program(unit_id: u64, program: &Program) -> Self {
+    /// Builds an executable around devices the caller already set up (e.g. seeded), so the
+    /// program's reset vector sees them that way
+    pub fn with_io(unit_type_id: u64, program: &Program, mut device: UnitIO) -> Self {
         let ram = UxnRam::new();
         let mut uxn = Uxn::new(ram.leak(), Backend::Interpreter);
         uxn.reset(&program.rom);
@@ -41,12 +93,13 @@ impl Executable {
             translation: Vec3::new(0., 0., 0.),
             ..default()
         };
-        let mut device = UnitIO::new();
         let mut dev = device.arm(&mut transform);
         // Initialize the system
         uxn.run(&mut dev, 0x100);
 
-        let limits = CpuLimits { num_cycles: 10000 };
+        let limits = CpuLimits {
+            num_cycles: DEFAULT_CYCLE_BUDGET,
+        };
 
         Executable {
             cpu: uxn,
@@ -56,8 +109,76 @@ impl Executable {
             breakpoints: BTreeSet::new(),
             pc: None,
             vector_queue: Vec::new(),
-            unit_id,
+            unit_type_id,
+            version_id: None,
+            cycles_left: 0,
+            out_of_cycles: false,
+            handled_hops: None,
+        }
+    }
+
+    /// Rebuilds an executable from a snapshot, without running the reset vector. Host side
+    /// device state (timers, radio inbox...) starts out empty.
+    pub fn from_snapshot(
+        unit_type_id: u64,
+        program: &Program,
+        installed: InstalledDevices,
+        snapshot: &VmSnapshot,
+    ) -> Self {
+        let ram = UxnRam::new();
+        let mut uxn = Uxn::new(ram.leak(), Backend::Interpreter);
+
+        for (addr, byte) in snapshot.ram.iter().take(0x10000).enumerate() {
+            uxn.ram_write_byte(addr as u16, *byte);
+        }
+        for (i, page) in snapshot.devices.chunks_exact(DEV_SIZE).take(16).enumerate() {
+            uxn.dev_mut_at::<RawPorts>((i * DEV_SIZE) as u8)
+                .0
+                .copy_from_slice(page);
+        }
+        for byte in &snapshot.stack {
+            uxn.stack.push_byte(*byte);
+        }
+        for byte in &snapshot.ret {
+            uxn.ret.push_byte(*byte);
+        }
+
+        Executable {
+            cpu: uxn,
+            device: UnitIO::with_devices(installed),
+            limits: CpuLimits {
+                num_cycles: DEFAULT_CYCLE_BUDGET,
+            },
+            program: program.clone(),
+            breakpoints: BTreeSet::new(),
+            pc: None,
+            vector_queue: Vec::new(),
+            unit_type_id,
+            version_id: None,
             cycles_left: 0,
+            out_of_cycles: false,
+            handled_hops: None,
+        }
+    }
+
+    pub fn snapshot(&self) -> VmSnapshot {
+        // peek_byte_at counts down from the top of the stack
+        let stack = (0..self.cpu.stack.len())
+            .rev()
+            .map(|i| self.cpu.stack.peek_byte_at(i))
+            .collect();
+        let ret = (0..self.cpu.ret.len())
+            .rev()
+            .map(|i| self.cpu.ret.peek_byte_at(i))
+            .collect();
+
+        VmSnapshot {
+            ram: (0..=u16::MAX).map(|addr| self.cpu.ram_read_byte(addr)).collect(),
+            devices: (0..16)
+                .flat_map(|i| self.cpu.dev_at::<RawPorts>((i * DEV_SIZE) as u8).0)
+                .collect(),
+            stack,
+            ret,
         }
     }
 
@@ -68,6 +189,72 @@ impl Executable {
         self.cpu.run(&mut dev, 0x100);
     }
 
+    /// Loads a new program keeping what it can of the old one's memory. The reset vector
+    /// runs so the program registers its vectors again, then labeled regions found in both
+    /// programs get their old contents back. Stacks are emptied, pending vectors and timers
+    /// follow their label to its new address or are dropped if it's gone.
+    pub fn reload_preserving_state(
+        &mut self,
+        program: &Program,
+        transform: &mut Transform,
+    ) -> ReloadReport {
+        let plan = plan_state_migration(&self.program, program);
+        let moves: Vec<(u16, Vec<u8>)> = plan
+            .moves
+            .iter()
+            .map(|m| {
+                let bytes = (0..m.size)
+                    .map(|i| self.cpu.ram_read_byte(m.from.wrapping_add(i)))
+                    .collect();
+                (m.to, bytes)
+            })
+            .collect();
+
+        // Vectors are addresses in the old program, find where their labels ended up
+        let old_labels: BTreeMap<u16, &String> = self
+            .program
+            .symbol_table
+            .iter()
+            .map(|(name, addr)| (*addr, name))
+            .collect();
+        let relocate = |vector: u16| {
+            old_labels
+                .get(&vector)
+                .and_then(|name| program.symbol_table.get(*name))
+                .copied()
+        };
+        let vector_queue: Vec<u16> = self.vector_queue.iter().filter_map(|v| relocate(*v)).collect();
+        let mut slots = self.device.timer.slots;
+        for slot in slots.iter_mut() {
+            *slot = slot.and_then(|mut timer| {
+                timer.vector = relocate(timer.vector)?;
+                Some(timer)
+            });
+        }
+
+        self.load_program(program, transform);
+        for (to, bytes) in moves {
+            for (i, byte) in bytes.iter().enumerate() {
+                self.cpu.ram_write_byte(to.wrapping_add(i as u16), *byte);
+            }
+        }
+        self.pc = None;
+        self.out_of_cycles = false;
+        self.handled_hops = None;
+        self.vector_queue = vector_queue;
+        // Timers the reset vector just set up only give way to ones that were already pending
+        for (slot, pending) in self.device.timer.slots.iter_mut().zip(slots) {
+            if pending.is_some() {
+                *slot = pending;
+            }
+        }
+
+        ReloadReport {
+            migrated: plan.moves.into_iter().map(|m| m.name).collect(),
+            reset: plan.reset,
+        }
+    }
+
     pub fn add_breakpoint(&mut self, addr: &u16) {
         self.breakpoints.insert(*addr);
     }
@@ -83,31 +270,49 @@ impl Executable {
     pub fn step(&mut self, transform: &mut Transform) {
         let mut device = self.device.arm(transform);
         if let Some(pc) = self.pc {
-            self.cycles_left -= 1;
             self.pc = self.cpu.step(&mut device, pc);
+            self.cycles_left = self.cycles_left.saturating_sub(1 + device.cycles_used);
         }
     }
 
-    pub fn cont(&mut self, transform: &mut Transform) -> Option<RadioMessage> {
-        let mut radio_message = None;
+    /// Runs until the vector finishes, a breakpoint is hit or the cycles run out, returning
+    /// every radio message sent along the way
+    pub fn cont(&mut self, transform: &mut Transform) -> Vec<RadioMessage> {
+        let mut radio_messages = Vec::new();
+        self.out_of_cycles = false;
 
         while let Some(pc) = self.pc {
             if self.has_breakpoint_at(&pc) {
                 break;
             }
             if self.cycles_left == 0 {
+                self.out_of_cycles = true;
                 break;
             }
 
             let mut device = self.device.arm(transform);
             self.pc = self.cpu.step(&mut device, pc);
+            // Every instruction costs a cycle, device operations cost extra
+            self.cycles_left = self.cycles_left.saturating_sub(1 + device.cycles_used);
 
-            if let Some(rm) = device.radio_message {
-                radio_message = Some(rm);
+            if let Some(mut rm) = device.radio_message.take() {
+                if let Some(hops) = self.handled_hops {
+                    rm.hops = hops.saturating_add(1);
+                }
+                radio_messages.push(rm);
             }
         }
 
-        radio_message
+        if self.pc.is_none() {
+            self.handled_hops = None;
+        }
+
+        // Relays can't bounce packets around forever
+        let sent = radio_messages.len();
+        radio_messages.retain(|rm| rm.hops <= MAX_HOPS);
+        self.device.radio.dropped += (sent - radio_messages.len()) as u32;
+
+        radio_messages
     }
 
     pub fn start(&mut self) {
@@ -128,6 +333,58 @@ impl Executable {
         v.move_vector.get()
     }
 
+    pub fn attack_vector(&mut self) -> u16 {
+        let v = self.cpu.dev::<CommandPorts>();
+        v.attack_vector.get()
+    }
+
+    pub fn set_attack_command(&mut self, x: u16, y: u16, target: u16) {
+        let v = self.cpu.dev_mut::<CommandPorts>();
+        v.x.set(x);
+        v.y.set(y);
+        v.target.set(target);
+    }
+
+    pub fn hit_vector(&mut self) -> u16 {
+        let v = self.cpu.dev::<WeaponPorts>();
+        v.vector.get()
+    }
+
+    pub fn set_weapon_status(&mut self, status: u8) {
+        let v = self.cpu.dev_mut::<WeaponPorts>();
+        v.status = status;
+    }
+
+    pub fn set_attacker(&mut self, attacker: u16) {
+        let v = self.cpu.dev_mut::<WeaponPorts>();
+        v.attacker.set(attacker);
+    }
+
+    pub fn cargo_vector(&mut self) -> u16 {
+        let v = self.cpu.dev::<CargoPorts>();
+        v.vector.get()
+    }
+
+    pub fn storage_vector(&mut self) -> u16 {
+        let v = self.cpu.dev::<StoragePorts>();
+        v.vector.get()
+    }
+
+    /// Handles a click on one of the program's interface widgets
+    pub fn click_widget(&mut self, id: u8) {
+        self.device.interface.click(&mut self.cpu, id);
+
+        let v = self.cpu.dev::<InterfacePorts>().vector.get();
+        if v != 0 {
+            self.vector_queue.push(v);
+        }
+    }
+
+    pub fn create_vector(&mut self) -> u16 {
+        let v = self.cpu.dev::<CommandPorts>();
+        v.create_vector.get()
+    }
+
     pub fn target_pos(&mut self) -> Vec3 {
         let m = self.cpu.dev::<MovementPorts>();
         Vec3::new(m.tx.get() as f32, m.ty.get() as f32, 0.0)
@@ -144,15 +401,23 @@ impl Executable {
         v.vector.get()
     }
 
-    pub fn radio_frequency(&mut self) -> u8 {
+    pub fn scan_vector(&mut self) -> u16 {
+        let v = self.cpu.dev::<SensorPorts>();
+        v.vector.get()
+    }
+
+    pub fn radio_listens_on(&mut self, frequency: u8) -> bool {
+        let v = self.cpu.dev::<RadioPorts>();
+        self.device.radio.listens_on(v.freq, frequency)
+    }
+
+    pub fn radio_enabled(&mut self) -> bool {
         let v = self.cpu.dev::<RadioPorts>();
-        v.freq
+        v.enabled != 0 && self.device.installed.is_installed(RadioPorts::BASE)
     }
 
-    pub fn set_radio_packets(&mut self, packets: &[u16; 2]) {
-        let v = self.cpu.dev_mut::<RadioPorts>();
-        v.packeth.set(packets[0]);
-        v.packetl.set(packets[1]);
+    pub fn deliver_radio_packet(&mut self, packet: &ReceivedPacket) {
+        self.device.radio.deliver(&mut self.cpu, packet);
     }
 
     pub fn set_move_command_coords(&mut self, x: u16, y: u16) {
@@ -170,3 +435,64 @@ impl Executable {
         Transform::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cont_stops_when_out_of_cycles() {
+        let src = "|00 @Command &move-vector $2 &attack-vector $2 &create-vector $2 &x $2 &y $2 &loop-vector $2 &target $2
+            |100 ;on-loop .Command/loop-vector DEO2 BRK
+            @on-loop #00 @spin INC ,spin JMP";
+        let program = assemble(src.to_string()).unwrap();
+        let mut executable = Executable::from_program(1, &program);
+        let mut transform = Transform::default();
+
+        // The literal, then 4 times round INC LIT JMP
+        executable.limits.num_cycles = 13;
+        executable.cycles_left = executable.limits.num_cycles;
+        executable.pc = Some(executable.loop_vector());
+        executable.cont(&mut transform);
+
+        assert!(executable.out_of_cycles);
+        assert_eq!(executable.cycles_left, 0);
+        assert_eq!(executable.cpu.stack.peek_byte_at(0), 0x04);
+
+        // Next tick picks up where it stopped
+        executable.cycles_left = executable.limits.num_cycles;
+        executable.cont(&mut transform);
+
+        assert!(executable.out_of_cycles);
+        assert_eq!(executable.cpu.stack.peek_byte_at(0), 0x09);
+    }
+
+    #[test]
+    fn test_handler_keeps_hop_count_when_out_of_cycles() {
+        let src = "|20 @Radio &vector $2 &packeth $2 &packetl $2 &command $1 &freq $1 &strength $1 &enabled $1
+            |100 ;on-packet .Radio/vector DEO2 #01 .Radio/enabled DEO BRK
+            @on-packet #00 .Radio/command DEO #00 .Radio/command DEO BRK";
+        let program = assemble(src.to_string()).unwrap();
+        let mut executable = Executable::from_program(1, &program);
+        let mut transform = Transform::default();
+        // Only enough for one transmission per frame
+        executable.limits.num_cycles = 3;
+
+        let mut run_handler = |executable: &mut Executable, hops: u8| {
+            executable.handled_hops = Some(hops);
+            executable.pc = Some(executable.radio_message_vector());
+            let mut sent = Vec::new();
+            while executable.pc.is_some() {
+                executable.cycles_left = executable.limits.num_cycles;
+                sent.extend(executable.cont(&mut transform).into_iter().map(|rm| rm.hops));
+            }
+            sent
+        };
+
+        assert_eq!(run_handler(&mut executable, 2), vec![3, 3]);
+        assert_eq!(executable.handled_hops, None);
+
+        assert!(run_handler(&mut executable, MAX_HOPS).is_empty());
+        assert_eq!(executable.device.radio.dropped, 2);
+    }
+}
@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+
+pub const DEFAULT_HIT_POINTS: u16 = 100;
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Health {
+    pub hp: u16,
+    pub max_hp: u16,
+    /// Flat amount subtracted from every hit taken
+    pub armor: u16,
+}
+
+impl Health {
+    pub fn new(max_hp: u16, armor: u16) -> Self {
+        Health {
+            hp: max_hp,
+            max_hp,
+            armor,
+        }
+    }
+
+    /// Applies a hit and returns the damage actually taken
+    pub fn take_hit(&mut self, damage: u16) -> u16 {
+        let taken = damage.saturating_sub(self.armor).max(1).min(self.hp);
+        self.hp -= taken;
+        taken
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.hp == 0
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health::new(DEFAULT_HIT_POINTS, 0)
+    }
+}
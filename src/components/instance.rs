@@ -0,0 +1,28 @@
+use bevy::prelude::*;
+
+/// Short handle identifying a single unit, used by programs to address each other
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct InstanceId(pub u16);
+
+/// Key of a single unit that, unlike its `InstanceId`, is never reused, not even in later
+/// sessions. The unit's own storage is kept under it.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct InstanceKey(pub u64);
+
+/// Hands out instance ids, starting at 1 so 0 can mean "no unit"
+#[derive(Resource)]
+pub struct NextInstanceId(pub u16);
+
+impl Default for NextInstanceId {
+    fn default() -> Self {
+        NextInstanceId(1)
+    }
+}
+
+impl NextInstanceId {
+    pub fn next(&mut self) -> InstanceId {
+        let id = InstanceId(self.0);
+        self.0 = self.0.wrapping_add(1).max(1);
+        id
+    }
+}
@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+
+pub const DEFAULT_CARGO_CAPACITY: u16 = 100;
+
+/// Resources a unit is carrying
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Inventory {
+    pub carried: u16,
+    pub capacity: u16,
+}
+
+impl Inventory {
+    pub fn new(capacity: u16) -> Self {
+        Inventory {
+            carried: 0,
+            capacity,
+        }
+    }
+
+    pub fn space_left(&self) -> u16 {
+        self.capacity - self.carried
+    }
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Inventory::new(DEFAULT_CARGO_CAPACITY)
+    }
+}
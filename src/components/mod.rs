@@ -1,7 +1,19 @@
 pub mod collider;
 pub mod executable;
+pub mod health;
+pub mod instance;
+pub mod inventory;
+pub mod resource_node;
 pub mod selectable;
+pub mod speed;
+pub mod team;
 
 pub use collider::Collider;
 pub use executable::Executable;
+pub use health::Health;
+pub use instance::{InstanceId, InstanceKey, NextInstanceId};
+pub use inventory::Inventory;
+pub use resource_node::{Depot, ResourceNode};
 pub use selectable::{Selectable, Selected};
+pub use speed::MaxSpeed;
+pub use team::Team;
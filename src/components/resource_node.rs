@@ -0,0 +1,11 @@
+use bevy::prelude::*;
+
+/// A spot on the map resources can be harvested from
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ResourceNode {
+    pub amount: u16,
+}
+
+/// A building where units drop off what they harvested for their team
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Depot;
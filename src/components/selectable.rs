@@ -22,6 +22,13 @@ impl Selectable {
         }
     }
 
+    pub fn building() -> Self {
+        Selectable {
+            ty: SelectableType::Building,
+            selected: false,
+        }
+    }
+
     pub fn select(&mut self, _commands: &mut Commands) {
         self.selected = true;
     }
@@ -0,0 +1,13 @@
+use bevy::prelude::*;
+
+pub const DEFAULT_MAX_SPEED: f32 = 10.0;
+
+/// Most distance a unit covers in a single tick
+#[derive(Component, Clone, Copy, Debug)]
+pub struct MaxSpeed(pub f32);
+
+impl Default for MaxSpeed {
+    fn default() -> Self {
+        MaxSpeed(DEFAULT_MAX_SPEED)
+    }
+}
@@ -0,0 +1,5 @@
+use bevy::prelude::*;
+
+/// The team a unit belongs to, used to tell friends from foes.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Team(pub u8);
@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+use crate::components::Executable;
+
+/// Environment variable pointing to a file where every unit's console output is appended
+pub const CONSOLE_LOG_ENV: &str = "KIKAI_CONSOLE_LOG";
+
+#[derive(Resource, Default)]
+pub struct ConsoleLogFile {
+    file: Option<File>,
+}
+
+impl ConsoleLogFile {
+    pub fn from_env() -> Self {
+        let file = std::env::var(CONSOLE_LOG_ENV).ok().and_then(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .inspect_err(|e| eprintln!("Couldn't open console log {}: {}", path, e))
+                .ok()
+        });
+
+        ConsoleLogFile { file }
+    }
+}
+
+fn flush_console_output(
+    mut query: Query<(Entity, &mut Executable)>,
+    mut log_file: ResMut<ConsoleLogFile>,
+) {
+    for (entity, mut executable) in &mut query {
+        let lines = std::mem::take(&mut executable.device.console.unflushed);
+
+        if let Some(file) = log_file.file.as_mut() {
+            for line in lines {
+                let stream = if line.is_error { "err" } else { "out" };
+                let _ = writeln!(
+                    file,
+                    "[{} type={}] {}: {}",
+                    entity, executable.unit_type_id, stream, line.text
+                );
+            }
+        }
+    }
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ConsoleLogFile::from_env())
+            .add_systems(PostUpdate, flush_console_output);
+    }
+}
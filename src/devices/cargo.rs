@@ -0,0 +1,94 @@
+///! The Cargo device lets a unit harvest resources and bring them back to a depot.
+///!
+///! Writing to `command` acts on whatever is within reach of the unit:
+///!
+///! 01 -> harvest from the closest resource node
+///! 02 -> deposit everything carried in the closest friendly depot
+///! 03 -> query how much is left in the closest resource node
+///!
+///! Once the command is resolved `carried`, `node` and `status` are updated and the
+///! `vector` is called.
+use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use zerocopy::{BigEndian, U16};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+pub const STATUS_OK: u8 = 0x00;
+pub const STATUS_NOTHING_IN_REACH: u8 = 0x01;
+pub const STATUS_FULL: u8 = 0x02;
+pub const STATUS_EMPTY: u8 = 0x03;
+
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct CargoPorts {
+    // |90 @Cargo &vector $2 &carried $2 &capacity $2 &node $2 &command $1 &status $1
+    pub vector: U16<BigEndian>,
+    pub carried: U16<BigEndian>,
+    pub capacity: U16<BigEndian>,
+    pub node: U16<BigEndian>,
+    pub command: u8,
+    pub status: u8,
+    _p1: u16,
+    _p2: u32,
+}
+
+impl CargoPorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for CargoPorts {
+    const BASE: u8 = 0x90;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CargoCommand {
+    Harvest,
+    Deposit,
+    Query,
+}
+
+pub struct Cargo {
+    /// Command issued by the program that has not been resolved yet
+    pub pending_command: Option<CargoCommand>,
+}
+
+impl Cargo {
+    pub fn new() -> Self {
+        Cargo {
+            pending_command: None,
+        }
+    }
+
+    pub fn deo(&mut self, vm: &mut Uxn, target: u8) {
+        if target & 0x0F != 0x08 {
+            return;
+        }
+
+        let d = vm.dev::<CargoPorts>();
+        self.pending_command = match d.command {
+            0x01 => Some(CargoCommand::Harvest),
+            0x02 => Some(CargoCommand::Deposit),
+            0x03 => Some(CargoCommand::Query),
+            _ => {
+                println!("UNKNOWN CARGO COMMAND");
+                None
+            }
+        };
+    }
+
+    /// Reports the result of a command back to the program
+    pub fn complete(&mut self, vm: &mut Uxn, carried: u16, capacity: u16, node: u16, status: u8) {
+        let d = vm.dev_mut::<CargoPorts>();
+        d.carried.set(carried);
+        d.capacity.set(capacity);
+        d.node.set(node);
+        d.status = status;
+    }
+}
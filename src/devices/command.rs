@@ -2,7 +2,7 @@
 ///! It exposes several vectors that are core to the Kikai control loop
 ///!
 ///! move_vector -> Called when the unit is given a move command (maybe move it to a radio device?)
-///! attack_vector -> Called when the unit is given an attack command (maybe move it to a radio device?)
+///! attack_vector -> Called when the unit is given an attack command, with the enemy handle in target
 ///! loop_vector -> This is the main loop vector for repetitive tasks
 use raven_uxn::{Ports, Uxn, DEV_SIZE};
 use zerocopy::{BigEndian, U16};
@@ -17,7 +17,8 @@ pub struct CommandPorts {
     pub x: U16<BigEndian>,
     pub y: U16<BigEndian>,
     pub loop_vector: U16<BigEndian>,
-    _padding: u32,
+    pub target: U16<BigEndian>,
+    _padding: u16,
 }
 
 impl Ports for CommandPorts {
@@ -0,0 +1,136 @@
+///! The Console device gives unit programs a way to print debug output.
+///!
+///! It follows the Varvara Console layout, so bytes written to `write` end up in the
+///! unit's output and bytes written to `error` end up in its error output. Output is
+///! split in lines and kept in a ring buffer that the Unit Inspector displays.
+use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use std::collections::VecDeque;
+use zerocopy::{BigEndian, U16};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+/// Maximum number of lines kept per unit
+pub const CONSOLE_CAPACITY: usize = 256;
+
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct ConsolePorts {
+    // |40 @Console &vector $2 &read $1 &pad $5 &write $1 &error $1
+    pub vector: U16<BigEndian>,
+    pub read: u8,
+    _pad: [u8; 5],
+    pub write: u8,
+    pub error: u8,
+    _p1: u16,
+    _p2: u32,
+}
+
+impl ConsolePorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for ConsolePorts {
+    const BASE: u8 = 0x40;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConsoleLine {
+    pub text: String,
+    pub is_error: bool,
+}
+
+pub struct Console {
+    pub lines: VecDeque<ConsoleLine>,
+    /// Lines completed since the last time they were written to the log file
+    pub unflushed: Vec<ConsoleLine>,
+    out_partial: String,
+    err_partial: String,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Console {
+            lines: VecDeque::new(),
+            unflushed: Vec::new(),
+            out_partial: String::new(),
+            err_partial: String::new(),
+        }
+    }
+
+    pub fn deo(&mut self, vm: &mut Uxn, target: u8) {
+        let d = vm.dev::<ConsolePorts>();
+        match target & 0x0F {
+            0x08 => self.push_byte(d.write, false),
+            0x09 => self.push_byte(d.error, true),
+            _ => {}
+        }
+    }
+
+    pub fn push_byte(&mut self, byte: u8, is_error: bool) {
+        let partial = if is_error {
+            &mut self.err_partial
+        } else {
+            &mut self.out_partial
+        };
+
+        if byte == b'\n' {
+            let line = ConsoleLine {
+                text: std::mem::take(partial),
+                is_error,
+            };
+            self.push_line(line);
+        } else {
+            partial.push(byte as char);
+        }
+    }
+
+    fn push_line(&mut self, line: ConsoleLine) {
+        if self.lines.len() == CONSOLE_CAPACITY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.clone());
+        self.unflushed.push(line);
+    }
+
+    /// Text written to the output and error streams since their last newline
+    pub fn pending_text(&self) -> (&str, &str) {
+        (&self.out_partial, &self.err_partial)
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.out_partial.clear();
+        self.err_partial.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_splits_lines_and_drops_oldest() {
+        let mut console = Console::new();
+
+        for i in 0..CONSOLE_CAPACITY + 1 {
+            for b in format!("line {}\n", i).bytes() {
+                console.push_byte(b, false);
+            }
+        }
+        for b in "oops".bytes() {
+            console.push_byte(b, true);
+        }
+
+        assert_eq!(console.lines.len(), CONSOLE_CAPACITY);
+        assert_eq!(console.lines.front().unwrap().text, "line 1");
+        assert_eq!(console.pending_text(), ("", "oops"));
+        assert_eq!(console.unflushed.len(), CONSOLE_CAPACITY + 1);
+    }
+}
@@ -0,0 +1,184 @@
+///! The Factory device lets a unit build other units.
+///!
+///! Writing 01 to `command` orders a unit of type `type` to be built at `dx`,`dy` from
+///! the factory, writing 02 cancels the current order. Building takes resources from the
+///! unit's team and some time, `progress` goes from 00 to 64 (100%) while it's underway.
+///! Cancelling a job that is underway gives the resources back to the team.
+///! Once the new unit is out, its handle is left in `child` and the Command
+///! `create_vector` is called. If it can't be spawned after all, e.g. its code was
+///! broken while it was being built, the resources are given back, `child` is 0000,
+///! `status` is 05 and `create_vector` is called all the same.
+use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use zerocopy::{BigEndian, U16};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+pub const BUILD_COST: u32 = 50;
+pub const BUILD_TICKS: u32 = 120;
+
+pub const STATUS_IDLE: u8 = 0x00;
+pub const STATUS_BUILDING: u8 = 0x01;
+pub const STATUS_NO_RESOURCES: u8 = 0x02;
+pub const STATUS_BUSY: u8 = 0x03;
+pub const STATUS_UNKNOWN_TYPE: u8 = 0x04;
+pub const STATUS_SPAWN_FAILED: u8 = 0x05;
+
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct FactoryPorts {
+    // |80 @Factory &type $2 &dx $2 &dy $2 &child $2 &progress $1 &command $1 &status $1
+    pub unit_type: U16<BigEndian>,
+    pub dx: U16<BigEndian>,
+    pub dy: U16<BigEndian>,
+    pub child: U16<BigEndian>,
+    pub progress: u8,
+    pub command: u8,
+    pub status: u8,
+    _p1: u8,
+    _p2: u32,
+}
+
+impl FactoryPorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for FactoryPorts {
+    const BASE: u8 = 0x80;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BuildOrder {
+    pub unit_type: u16,
+    pub dx: i16,
+    pub dy: i16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BuildJob {
+    pub order: BuildOrder,
+    pub ticks_left: u32,
+}
+
+pub struct Factory {
+    /// Order placed by the program that has not been accepted yet
+    pub pending_order: Option<BuildOrder>,
+    pub job: Option<BuildJob>,
+    /// Set when a paid for job was cancelled and the team is owed its cost back
+    pub refund_due: bool,
+}
+
+impl Factory {
+    pub fn new() -> Self {
+        Factory {
+            pending_order: None,
+            job: None,
+            refund_due: false,
+        }
+    }
+
+    pub fn deo(&mut self, vm: &mut Uxn, target: u8) {
+        if target & 0x0F != 0x09 {
+            return;
+        }
+
+        let d = vm.dev_mut::<FactoryPorts>();
+        match d.command {
+            0x01 => {
+                if self.job.is_some() {
+                    d.status = STATUS_BUSY;
+                    return;
+                }
+                self.pending_order = Some(BuildOrder {
+                    unit_type: d.unit_type.get(),
+                    dx: d.dx.get() as i16,
+                    dy: d.dy.get() as i16,
+                });
+            }
+            0x02 => {
+                self.pending_order = None;
+                if self.job.take().is_some() {
+                    self.refund_due = true;
+                }
+                d.progress = 0;
+                d.status = STATUS_IDLE;
+            }
+            _ => {
+                println!("UNKNOWN FACTORY COMMAND");
+            }
+        }
+    }
+
+    /// Starts building `order`, the caller has already checked it can be paid for
+    pub fn start(&mut self, vm: &mut Uxn, order: BuildOrder) {
+        self.job = Some(BuildJob {
+            order,
+            ticks_left: BUILD_TICKS,
+        });
+        let d = vm.dev_mut::<FactoryPorts>();
+        d.progress = 0;
+        d.status = STATUS_BUILDING;
+    }
+
+    /// Advances the current job and returns its order once it's done
+    pub fn tick(&mut self, vm: &mut Uxn) -> Option<BuildOrder> {
+        let job = self.job.as_mut()?;
+        job.ticks_left = job.ticks_left.saturating_sub(1);
+
+        let d = vm.dev_mut::<FactoryPorts>();
+        d.progress = (100 - job.ticks_left * 100 / BUILD_TICKS) as u8;
+
+        if job.ticks_left == 0 {
+            let order = job.order;
+            self.job = None;
+            d.status = STATUS_IDLE;
+            Some(order)
+        } else {
+            None
+        }
+    }
+
+    pub fn reject(&mut self, vm: &mut Uxn, status: u8) {
+        vm.dev_mut::<FactoryPorts>().status = status;
+    }
+
+    pub fn set_child(&mut self, vm: &mut Uxn, child: u16) {
+        vm.dev_mut::<FactoryPorts>().child.set(child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raven_uxn::{Backend, UxnRam};
+
+    #[test]
+    fn test_cancel_refunds_running_job() {
+        let mut vm = Uxn::new(UxnRam::new().leak(), Backend::Interpreter);
+        let mut factory = Factory::new();
+        let order = BuildOrder {
+            unit_type: 1,
+            dx: 0,
+            dy: 0,
+        };
+
+        vm.dev_mut::<FactoryPorts>().command = 0x02;
+        factory.deo(&mut vm, FactoryPorts::BASE | 0x09);
+        assert!(!factory.refund_due);
+
+        factory.start(&mut vm, order);
+        factory.tick(&mut vm);
+        factory.deo(&mut vm, FactoryPorts::BASE | 0x09);
+
+        assert!(factory.refund_due);
+        assert_eq!(factory.job, None);
+        assert_eq!(vm.dev::<FactoryPorts>().status, STATUS_IDLE);
+        assert_eq!(vm.dev::<FactoryPorts>().progress, 0);
+    }
+}
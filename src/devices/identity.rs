@@ -0,0 +1,69 @@
+///! The Identity device tells a unit program who it is.
+///!
+///! `id` is the handle of this particular unit, stable for as long as it lives, and is
+///! the same handle other devices use to address units. `type` is the unit type,
+///! `team` the team it belongs to and `spawnh`/`spawnl` the tick it was spawned at.
+///! `hp` and `max-hp` report its current health.
+use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use zerocopy::{BigEndian, U16};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct IdentityPorts {
+    // |a0 @Identity &id $2 &type $2 &team $1 &pad $1 &spawnh $2 &spawnl $2 &hp $2 &max-hp $2
+    pub id: U16<BigEndian>,
+    pub unit_type: U16<BigEndian>,
+    pub team: u8,
+    _pad: u8,
+    pub spawnh: U16<BigEndian>,
+    pub spawnl: U16<BigEndian>,
+    pub hp: U16<BigEndian>,
+    pub max_hp: U16<BigEndian>,
+    _p1: u16,
+}
+
+impl IdentityPorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for IdentityPorts {
+    const BASE: u8 = 0xA0;
+}
+
+/// Filled in when the unit is spawned, before its reset vector runs; health is kept up to
+/// date by the identity system afterwards
+#[derive(Default)]
+pub struct Identity {
+    pub instance_id: u16,
+    pub unit_type: u16,
+    pub team: u8,
+    pub spawn_tick: u64,
+    pub hp: u16,
+    pub max_hp: u16,
+}
+
+impl Identity {
+    pub fn new() -> Self {
+        Identity::default()
+    }
+
+    pub fn dei(&mut self, vm: &mut Uxn, _target: u8) {
+        let d = vm.dev_mut::<IdentityPorts>();
+        d.id.set(self.instance_id);
+        d.unit_type.set(self.unit_type);
+        d.team = self.team;
+        d.spawnh.set((self.spawn_tick >> 16) as u16);
+        d.spawnl.set(self.spawn_tick as u16);
+        d.hp.set(self.hp);
+        d.max_hp.set(self.max_hp);
+    }
+}
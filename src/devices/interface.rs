@@ -0,0 +1,130 @@
+///! The Interface device lets a unit program build its own control panel, shown in the
+///! Unit Inspector while the unit is selected.
+///!
+///! To declare a widget set `id`, `kind` (01 label, 02 button, 03 toggle), `text` (address
+///! of a null terminated string in RAM) and `value` (initial state of toggles), then
+///! write 01 to `command`. Declaring an existing id replaces it. 02 removes the widget
+///! with `id` and 03 removes every widget.
+///!
+///! When a button or toggle is clicked, `id` and `value` are set to the widget's and the
+///! `vector` is called.
+use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use std::collections::BTreeMap;
+use zerocopy::{BigEndian, U16};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+/// Longest widget text accepted, longer strings are truncated
+pub const MAX_TEXT_LEN: usize = 32;
+
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct InterfacePorts {
+    // |c0 @Interface &vector $2 &text $2 &id $1 &kind $1 &value $1 &command $1
+    pub vector: U16<BigEndian>,
+    pub text: U16<BigEndian>,
+    pub id: u8,
+    pub kind: u8,
+    pub value: u8,
+    pub command: u8,
+    _p1: u64,
+}
+
+impl InterfacePorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for InterfacePorts {
+    const BASE: u8 = 0xC0;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WidgetKind {
+    Label,
+    Button,
+    Toggle,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Widget {
+    pub kind: WidgetKind,
+    pub text: String,
+    pub value: u8,
+}
+
+pub struct Interface {
+    /// Declared widgets by id, drawn in id order
+    pub widgets: BTreeMap<u8, Widget>,
+}
+
+impl Interface {
+    pub fn new() -> Self {
+        Interface {
+            widgets: BTreeMap::new(),
+        }
+    }
+
+    pub fn deo(&mut self, vm: &mut Uxn, target: u8) {
+        if target & 0x0F != 0x07 {
+            return;
+        }
+
+        let d = vm.dev::<InterfacePorts>();
+        let id = d.id;
+        match d.command {
+            0x01 => {
+                let kind = match d.kind {
+                    0x01 => WidgetKind::Label,
+                    0x02 => WidgetKind::Button,
+                    0x03 => WidgetKind::Toggle,
+                    _ => {
+                        println!("UNKNOWN WIDGET KIND");
+                        return;
+                    }
+                };
+                let value = d.value;
+                let text = Self::read_text(vm, d.text.get());
+                self.widgets.insert(id, Widget { kind, text, value });
+            }
+            0x02 => {
+                self.widgets.remove(&id);
+            }
+            0x03 => {
+                self.widgets.clear();
+            }
+            _ => {
+                println!("UNKNOWN INTERFACE COMMAND");
+            }
+        }
+    }
+
+    fn read_text(vm: &Uxn, addr: u16) -> String {
+        (0..MAX_TEXT_LEN as u16)
+            .map(|i| vm.ram_read_byte(addr.wrapping_add(i)))
+            .take_while(|b| *b != 0)
+            .map(|b| b as char)
+            .collect()
+    }
+
+    /// Records a click on widget `id` and exposes it to the program, toggles flip their value
+    pub fn click(&mut self, vm: &mut Uxn, id: u8) {
+        let Some(widget) = self.widgets.get_mut(&id) else {
+            return;
+        };
+
+        if widget.kind == WidgetKind::Toggle {
+            widget.value = (widget.value == 0) as u8;
+        }
+
+        let d = vm.dev_mut::<InterfacePorts>();
+        d.id = id;
+        d.value = widget.value;
+    }
+}
@@ -1,34 +1,145 @@
 use bevy::prelude::*;
-use raven_uxn::{Device, Ports, Uxn};
+use raven_uxn::{Device, Ports, Uxn, DEV_SIZE};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
+pub mod cargo;
 pub mod command;
+pub mod console;
+pub mod factory;
+pub mod identity;
+pub mod interface;
 pub mod movement;
 pub mod radio;
+pub mod random;
+pub mod sensor;
+pub mod storage;
+pub mod timer;
+pub mod weapon;
 
+pub use cargo::{Cargo, CargoPorts};
 pub use command::{Command, CommandPorts};
+pub use console::{Console, ConsolePorts};
+pub use factory::{Factory, FactoryPorts};
+pub use identity::{Identity, IdentityPorts};
+pub use interface::{Interface, InterfacePorts};
 pub use movement::{Movement, MovementPorts};
-pub use radio::{Radio, RadioPorts};
+pub use radio::{Radio, RadioLinkPorts, RadioPorts};
+pub use random::{Random, RandomPorts};
+pub use sensor::{Sensor, SensorPorts};
+pub use storage::{Storage, StoragePorts};
+pub use timer::{Timer, TimerPorts};
+pub use weapon::{Weapon, WeaponPorts};
 
 use crate::radio::RadioMessage;
 
+/// A device's ports as plain bytes, for copying the device page around whole
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct RawPorts(pub [u8; DEV_SIZE]);
+
+impl Ports for RawPorts {
+    const BASE: u8 = 0x00;
+}
+
+/// Which devices a unit type has, one bit per device page. The Command device is always
+/// installed and the RadioLink page comes with the Radio.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InstalledDevices(pub u16);
+
+/// Devices that can be left out of a unit type, by name and page
+pub const OPTIONAL_DEVICES: [(&str, u8); 12] = [
+    ("Movement", MovementPorts::BASE),
+    ("Radio", RadioPorts::BASE),
+    ("Sensor", SensorPorts::BASE),
+    ("Console", ConsolePorts::BASE),
+    ("Timer", TimerPorts::BASE),
+    ("Random", RandomPorts::BASE),
+    ("Weapon", WeaponPorts::BASE),
+    ("Factory", FactoryPorts::BASE),
+    ("Cargo", CargoPorts::BASE),
+    ("Identity", IdentityPorts::BASE),
+    ("Storage", StoragePorts::BASE),
+    ("Interface", InterfacePorts::BASE),
+];
+
+impl InstalledDevices {
+    pub const ALL: InstalledDevices = InstalledDevices(u16::MAX);
+
+    fn bit(page: u8) -> u16 {
+        let page = if page == RadioLinkPorts::BASE {
+            RadioPorts::BASE
+        } else {
+            page
+        };
+        1 << (page >> 4)
+    }
+
+    pub fn is_installed(&self, page: u8) -> bool {
+        page == CommandPorts::BASE || self.0 & Self::bit(page) != 0
+    }
+
+    pub fn set(&mut self, page: u8, installed: bool) {
+        if installed {
+            self.0 |= Self::bit(page);
+        } else {
+            self.0 &= !Self::bit(page);
+        }
+    }
+}
+
+impl Default for InstalledDevices {
+    fn default() -> Self {
+        InstalledDevices::ALL
+    }
+}
+
 pub struct UnitIO {
+    /// Devices missing from the unit don't react to the program at all
+    pub installed: InstalledDevices,
     command: Command,
     movement: Movement,
-    radio: Radio,
+    pub radio: Radio,
+    pub sensor: Sensor,
+    pub console: Console,
+    pub timer: Timer,
+    pub random: Random,
+    pub weapon: Weapon,
+    pub factory: Factory,
+    pub cargo: Cargo,
+    pub identity: Identity,
+    pub storage: Storage,
+    pub interface: Interface,
 }
 
 pub struct ArmedUnitIO<'a> {
     pub transform: &'a mut Transform,
     pub radio_message: Option<RadioMessage>,
+    /// Cycles consumed by device operations during this step
+    pub cycles_used: u32,
     pub unit_io: &'a mut UnitIO,
 }
 
 impl UnitIO {
     pub fn new() -> Self {
+        UnitIO::with_devices(InstalledDevices::ALL)
+    }
+
+    pub fn with_devices(installed: InstalledDevices) -> Self {
         UnitIO {
+            installed,
             command: Command::new(),
             movement: Movement::new(),
             radio: Radio::new(),
+            sensor: Sensor::new(),
+            console: Console::new(),
+            timer: Timer::new(),
+            random: Random::new(),
+            weapon: Weapon::new(),
+            factory: Factory::new(),
+            cargo: Cargo::new(),
+            identity: Identity::new(),
+            storage: Storage::new(),
+            interface: Interface::new(),
         }
     }
 
@@ -36,6 +147,7 @@ impl UnitIO {
         ArmedUnitIO {
             transform,
             radio_message: None,
+            cycles_used: 0,
             unit_io: self,
         }
     }
@@ -43,13 +155,36 @@ impl UnitIO {
 
 impl Device for ArmedUnitIO<'_> {
     fn deo(&mut self, vm: &mut Uxn, target: u8) -> bool {
-        println!("TARGET: {}", target & 0xF0);
+        if !self.unit_io.installed.is_installed(target & 0xF0) {
+            return true;
+        }
+
         match target & 0xF0 {
             CommandPorts::BASE => self.unit_io.command.deo(vm, target),
             MovementPorts::BASE => self.unit_io.movement.deo(vm, target, self.transform),
             RadioPorts::BASE => {
-                self.radio_message = self.unit_io.radio.deo(vm, target);
+                if let Some(mut rm) = self.unit_io.radio.deo(vm, target) {
+                    rm.origin_position = self.transform.translation.xy();
+                    self.cycles_used += radio::transmission_cost(rm.strength, rm.payload.len());
+                    self.radio_message = Some(rm);
+                }
+            }
+            SensorPorts::BASE => {
+                self.cycles_used += self.unit_io.sensor.deo(vm, target);
             }
+            ConsolePorts::BASE => self.unit_io.console.deo(vm, target),
+            TimerPorts::BASE => self.unit_io.timer.deo(vm, target),
+            WeaponPorts::BASE => self.unit_io.weapon.deo(vm, target),
+            FactoryPorts::BASE => self.unit_io.factory.deo(vm, target),
+            CargoPorts::BASE => self.unit_io.cargo.deo(vm, target),
+            StoragePorts::BASE => self.unit_io.storage.deo(vm, target),
+            InterfacePorts::BASE => self.unit_io.interface.deo(vm, target),
+            // The link page only holds buffers, commands go through the radio page
+            RadioLinkPorts::BASE => {}
+            // Numbers are only handed out on reads
+            RandomPorts::BASE => {}
+            // Read-only, filled in from the unit's components
+            IdentityPorts::BASE => {}
             _ => {
                 println!("UNIMPLEMENTED DEVICE")
             }
@@ -57,7 +192,18 @@ impl Device for ArmedUnitIO<'_> {
         true
     }
 
-    fn dei(&mut self, _vm: &mut Uxn, target: u8) {
-        println!("DEI {}", target);
+    fn dei(&mut self, vm: &mut Uxn, target: u8) {
+        if !self.unit_io.installed.is_installed(target & 0xF0) {
+            return;
+        }
+
+        match target & 0xF0 {
+            TimerPorts::BASE => self.unit_io.timer.dei(vm, target),
+            RandomPorts::BASE => self.unit_io.random.dei(vm, target),
+            WeaponPorts::BASE => self.unit_io.weapon.dei(vm, target),
+            IdentityPorts::BASE => self.unit_io.identity.dei(vm, target),
+            RadioLinkPorts::BASE => self.unit_io.radio.dei(vm, target),
+            _ => {}
+        }
     }
 }
@@ -1,13 +1,64 @@
+///! The Radio device lets units talk to each other.
+///!
+///! Writing to `command` sends or configures the radio:
+///!
+///! - 00 broadcasts `packeth`/`packetl` on `freq`
+///! - 01 broadcasts `length` bytes of RAM starting at the RadioLink `addr` on `freq`
+///! - 02 sends the same buffer on `freq` to the unit whose handle is in `peer` only
+///! - 03 listens on `freq` as well as the frequency the radio is tuned to
+///! - 04 stops listening on `freq`
+///! - 05 listens on every frequency
+///! - 06 goes back to listening on the tuned frequency only
+///!
+///! The signal reaches further the higher `strength` is, but every transmission costs
+///! cycles in proportion, plus one per payload byte. Radios with `enabled` at 00 neither
+///! send nor receive.
+///!
+///! On reception the first four bytes of the payload are written to `packeth`/`packetl`,
+///! the received signal strength to `rssi`, the sender's handle to `sender`, the
+///! frequency to `rx-freq` and the payload length to `rx-length`. If `rx-addr` is set, up
+///! to `rx-max` bytes of the payload are copied there. Then the `vector` is called.
+///! Packets that arrive while the unit is busy wait in an inbox, reading `inbox` tells
+///! how many are waiting.
+use bevy::prelude::*;
 use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use std::collections::{BTreeSet, VecDeque};
 use zerocopy::{BigEndian, U16};
 use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 use crate::radio::RadioMessage;
 
+/// Distance covered by each point of `strength`
+pub const RANGE_PER_STRENGTH: f32 = 4.0;
+/// Cycles every transmission costs, on top of one per point of `strength`
+pub const TRANSMIT_BASE_COST: u32 = 10;
+
+/// Most packets a radio holds before it starts dropping new ones
+pub const INBOX_CAPACITY: usize = 16;
+/// Longest payload a single packet carries, longer buffers are truncated
+pub const MAX_PAYLOAD: usize = 64;
+
+pub fn range_for_strength(strength: u8) -> f32 {
+    strength as f32 * RANGE_PER_STRENGTH
+}
+
+/// Signal strength received at `distance` from a transmitter with the given range,
+/// falling off linearly to 0 at the edge of the range
+pub fn received_strength(distance: f32, range: f32) -> u8 {
+    if range <= 0.0 || distance > range {
+        return 0;
+    }
+    ((1.0 - distance / range) * 255.0).round() as u8
+}
+
+pub fn transmission_cost(strength: u8, payload_len: usize) -> u32 {
+    TRANSMIT_BASE_COST + strength as u32 + payload_len as u32
+}
+
 #[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
 #[repr(C)]
 pub struct RadioPorts {
-    // |30 @Radio &vector $2 &packeth $2 &packetl $2 &command $1 &freq $1 &strength $1 &enabled $1
+    // |20 @Radio &vector $2 &packeth $2 &packetl $2 &command $1 &freq $1 &strength $1 &enabled $1 &rssi $1
     pub vector: U16<BigEndian>,
     pub packeth: U16<BigEndian>,
     pub packetl: U16<BigEndian>,
@@ -15,7 +66,8 @@ pub struct RadioPorts {
     pub freq: u8,
     pub strength: u8,
     pub enabled: u8,
-    _p2: u16,
+    pub rssi: u8,
+    _p2: u8,
     _p1: u32,
 }
 
@@ -35,31 +87,133 @@ impl Ports for RadioPorts {
     const BASE: u8 = 0x20;
 }
 
-pub struct Radio {}
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct RadioLinkPorts {
+    // |d0 @RadioLink &addr $2 &length $1 &inbox $1 &peer $2 &sender $2 &rx-addr $2 &rx-max $1 &rx-length $1 &rx-freq $1
+    pub addr: U16<BigEndian>,
+    pub length: u8,
+    pub inbox: u8,
+    pub peer: U16<BigEndian>,
+    pub sender: U16<BigEndian>,
+    pub rx_addr: U16<BigEndian>,
+    pub rx_max: u8,
+    pub rx_length: u8,
+    pub rx_freq: u8,
+    _pad: [u8; 3],
+}
+
+impl RadioLinkPorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for RadioLinkPorts {
+    const BASE: u8 = 0xD0;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceivedPacket {
+    pub payload: Vec<u8>,
+    pub frequency: u8,
+    /// Handle of the unit that sent the packet, 0 if it didn't come from a unit
+    pub sender: u16,
+    pub rssi: u8,
+    /// Times the packet has been relayed before reaching this radio
+    pub hops: u8,
+}
+
+pub struct Radio {
+    /// Packets delivered to this radio that the program hasn't handled yet, oldest first
+    pub inbox: VecDeque<ReceivedPacket>,
+    /// Frequencies listened on besides the one the radio is tuned to
+    pub extra_frequencies: BTreeSet<u8>,
+    /// Listening on every frequency
+    pub scanning: bool,
+    pub sent: u32,
+    pub received: u32,
+    pub dropped: u32,
+}
 
 impl Radio {
     pub fn new() -> Self {
-        Radio {}
+        Radio {
+            inbox: VecDeque::new(),
+            extra_frequencies: BTreeSet::new(),
+            scanning: false,
+            sent: 0,
+            received: 0,
+            dropped: 0,
+        }
     }
 
+    pub fn listens_on(&self, tuned: u8, frequency: u8) -> bool {
+        self.scanning || tuned == frequency || self.extra_frequencies.contains(&frequency)
+    }
+
+    pub fn receive(&mut self, packet: ReceivedPacket) {
+        if self.inbox.len() >= INBOX_CAPACITY {
+            self.dropped += 1;
+        } else {
+            self.inbox.push_back(packet);
+            self.received += 1;
+        }
+    }
+
+    /// Returns the message to send, if any. The caller fills in where it comes from.
     pub fn deo(&mut self, vm: &mut Uxn, target: u8) -> Option<RadioMessage> {
         let d = vm.dev::<RadioPorts>();
         match target & 0x0F {
             6 => {
+                if d.enabled == 0 {
+                    return None;
+                }
+
+                let frequency = d.freq;
+                let strength = d.strength;
+
                 // Command
                 match d.command {
                     0 => {
-                        println!(
-                            "SENT RADIO PACKET AT FREQ {:X}: {:04X}{:04X}",
-                            d.freq,
-                            d.packeth.get(),
-                            d.packetl.get()
-                        );
-                        Some(RadioMessage {
-                            origin_entity_id: None,
-                            packets: [d.packeth.get(), d.packetl.get()],
-                            frequency: d.freq,
-                        })
+                        let mut payload = Vec::with_capacity(4);
+                        payload.extend_from_slice(&d.packeth.get().to_be_bytes());
+                        payload.extend_from_slice(&d.packetl.get().to_be_bytes());
+                        Some(RadioMessage::new(payload, frequency, strength, None))
+                    }
+                    1 | 2 => {
+                        let command = d.command;
+                        let link = vm.dev::<RadioLinkPorts>();
+                        let addr = link.addr.get();
+                        let length = (link.length as usize).min(MAX_PAYLOAD);
+                        let destination = (command == 2).then(|| link.peer.get());
+                        let payload = (0..length as u16)
+                            .map(|i| vm.ram_read_byte(addr.wrapping_add(i)))
+                            .collect();
+                        Some(RadioMessage::new(payload, frequency, strength, destination))
+                    }
+                    3 => {
+                        self.extra_frequencies.insert(frequency);
+                        None
+                    }
+                    4 => {
+                        self.extra_frequencies.remove(&frequency);
+                        None
+                    }
+                    5 => {
+                        self.scanning = true;
+                        None
+                    }
+                    6 => {
+                        self.extra_frequencies.clear();
+                        self.scanning = false;
+                        None
                     }
                     _ => {
                         println!("UNKNOWN COMMAND");
@@ -70,4 +224,66 @@ impl Radio {
             _ => None,
         }
     }
+
+    pub fn dei(&mut self, vm: &mut Uxn, target: u8) {
+        if target & 0xF0 == RadioLinkPorts::BASE && target & 0x0F == 0x03 {
+            vm.dev_mut::<RadioLinkPorts>().inbox = self.inbox.len() as u8;
+        }
+    }
+
+    /// Exposes a packet to the program, copying its payload into the receive buffer
+    pub fn deliver(&mut self, vm: &mut Uxn, packet: &ReceivedPacket) {
+        let mut packets = [0u8; 4];
+        for (dst, src) in packets.iter_mut().zip(&packet.payload) {
+            *dst = *src;
+        }
+
+        let d = vm.dev_mut::<RadioPorts>();
+        d.packeth.set(u16::from_be_bytes([packets[0], packets[1]]));
+        d.packetl.set(u16::from_be_bytes([packets[2], packets[3]]));
+        d.rssi = packet.rssi;
+
+        let link = vm.dev_mut::<RadioLinkPorts>();
+        link.sender.set(packet.sender);
+        link.rx_freq = packet.frequency;
+        link.rx_length = packet.payload.len() as u8;
+        let rx_addr = link.rx_addr.get();
+        let rx_max = link.rx_max as usize;
+
+        if rx_addr != 0 {
+            for (i, byte) in packet.payload.iter().take(rx_max).enumerate() {
+                vm.ram_write_byte(rx_addr.wrapping_add(i as u16), *byte);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_received_strength_attenuates_with_distance() {
+        let range = range_for_strength(100);
+
+        assert_eq!(received_strength(0.0, range), 255);
+        assert_eq!(received_strength(range / 2.0, range), 128);
+        assert_eq!(received_strength(range, range), 0);
+        assert_eq!(received_strength(range + 1.0, range), 0);
+        assert_eq!(received_strength(0.0, range_for_strength(0)), 0);
+    }
+
+    #[test]
+    fn test_listens_on_extra_frequencies() {
+        let mut radio = Radio::new();
+        assert!(radio.listens_on(0x10, 0x10));
+        assert!(!radio.listens_on(0x10, 0x20));
+
+        radio.extra_frequencies.insert(0x20);
+        assert!(radio.listens_on(0x10, 0x20));
+        assert!(!radio.listens_on(0x10, 0x30));
+
+        radio.scanning = true;
+        assert!(radio.listens_on(0x10, 0x30));
+    }
 }
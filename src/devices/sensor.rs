@@ -0,0 +1,129 @@
+///! The Sensor device lets a unit perceive what is around it.
+///!
+///! Writing 01 to `command` requests a scan of everything within `radius`. The scan
+///! is resolved at the end of the frame, after which `count` holds the number of
+///! results and the `vector` is called. Writing an index to `index` loads that result
+///! into `dx`, `dy`, `type`, `kind` and `friend`. For resource nodes `type` holds the
+///! amount left in the node.
+use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use zerocopy::{BigEndian, U16};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+/// Cycles charged to the unit for every scan it requests
+pub const SCAN_CYCLE_COST: u32 = 100;
+
+pub const KIND_NONE: u8 = 0x00;
+pub const KIND_UNIT: u8 = 0x01;
+pub const KIND_RESOURCE: u8 = 0x02;
+pub const KIND_DEPOT: u8 = 0x03;
+
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct SensorPorts {
+    // |30 @Sensor &vector $2 &radius $2 &dx $2 &dy $2 &type $2 &count $1 &index $1 &kind $1 &friend $1 &command $1 &pad $1
+    pub vector: U16<BigEndian>,
+    pub radius: U16<BigEndian>,
+    pub dx: U16<BigEndian>,
+    pub dy: U16<BigEndian>,
+    pub unit_type: U16<BigEndian>,
+    pub count: u8,
+    pub index: u8,
+    pub kind: u8,
+    pub friend: u8,
+    pub command: u8,
+    _p1: u8,
+}
+
+impl SensorPorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for SensorPorts {
+    const BASE: u8 = 0x30;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScanResult {
+    pub dx: i16,
+    pub dy: i16,
+    pub kind: u8,
+    pub unit_type: u16,
+    pub friend: bool,
+}
+
+pub struct Sensor {
+    /// Radius of a scan requested by the program that has not been resolved yet
+    pub pending_scan: Option<u16>,
+    pub results: Vec<ScanResult>,
+}
+
+impl Sensor {
+    pub fn new() -> Self {
+        Sensor {
+            pending_scan: None,
+            results: Vec::new(),
+        }
+    }
+
+    /// Returns the number of cycles the operation cost
+    pub fn deo(&mut self, vm: &mut Uxn, target: u8) -> u32 {
+        match target & 0x0F {
+            0x0B => {
+                let d = vm.dev_mut::<SensorPorts>();
+                let result = self.results.get(d.index as usize).copied();
+                Self::load_result(d, result);
+                0
+            }
+            0x0E => {
+                let d = vm.dev::<SensorPorts>();
+                match d.command {
+                    0x01 => {
+                        self.pending_scan = Some(d.radius.get());
+                        SCAN_CYCLE_COST
+                    }
+                    _ => {
+                        println!("UNKNOWN SENSOR COMMAND");
+                        0
+                    }
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    /// Stores the results of a resolved scan and exposes them to the program
+    pub fn complete_scan(&mut self, vm: &mut Uxn, results: Vec<ScanResult>) {
+        self.results = results;
+        let d = vm.dev_mut::<SensorPorts>();
+        d.count = self.results.len().min(u8::MAX as usize) as u8;
+        d.index = 0;
+        Self::load_result(d, self.results.first().copied());
+    }
+
+    fn load_result(d: &mut SensorPorts, result: Option<ScanResult>) {
+        match result {
+            Some(r) => {
+                d.dx.set(r.dx as u16);
+                d.dy.set(r.dy as u16);
+                d.unit_type.set(r.unit_type);
+                d.kind = r.kind;
+                d.friend = r.friend as u8;
+            }
+            None => {
+                d.dx.set(0);
+                d.dy.set(0);
+                d.unit_type.set(0);
+                d.kind = KIND_NONE;
+                d.friend = 0;
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+///! The Storage device gives unit programs named blobs that survive code reloads,
+///! respawns and restarts. It's modelled after the Varvara File device.
+///!
+///! `name` points to a null terminated string in RAM naming the blob. Writing an address
+///! to `read` loads up to `length` bytes of the blob there, writing an address to `write`
+///! stores `length` bytes from there into the blob (appending if `append` is 01) and
+///! writing to `delete` removes it.
+///!
+///! Blobs are shared by every unit of the same type, unless `scope` is 01, in which case
+///! they belong to this unit only. Per-unit blobs are kept under the unit's instance key,
+///! which is never reused, and travel with the unit in world saves. Requests are served at the end of the frame, after
+///! which `success` holds the number of bytes read or written (0 on failure) and the
+///! `vector` is called.
+use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use zerocopy::{BigEndian, U16};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+/// Longest blob name accepted, longer names are truncated
+pub const MAX_NAME_LEN: usize = 64;
+
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct StoragePorts {
+    // |b0 @Storage &vector $2 &success $2 &scope $1 &pad $1 &delete $1 &append $1 &name $2 &length $2 &read $2 &write $2
+    pub vector: U16<BigEndian>,
+    pub success: U16<BigEndian>,
+    pub scope: u8,
+    _pad: u8,
+    pub delete: u8,
+    pub append: u8,
+    pub name: U16<BigEndian>,
+    pub length: U16<BigEndian>,
+    pub read: U16<BigEndian>,
+    pub write: U16<BigEndian>,
+}
+
+impl StoragePorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for StoragePorts {
+    const BASE: u8 = 0xB0;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StorageOp {
+    Read { addr: u16, length: u16 },
+    Write { data: Vec<u8>, append: bool },
+    Delete,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageRequest {
+    pub name: String,
+    pub per_instance: bool,
+    pub op: StorageOp,
+}
+
+pub struct Storage {
+    /// Requests made by the program that have not been served yet, oldest first
+    pub pending: Vec<StorageRequest>,
+}
+
+impl Storage {
+    pub fn new() -> Self {
+        Storage {
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn deo(&mut self, vm: &mut Uxn, target: u8) {
+        let d = vm.dev::<StoragePorts>();
+        let name_addr = d.name.get();
+        let length = d.length.get();
+        let per_instance = d.scope == 0x01;
+
+        let op = match target & 0x0F {
+            0x06 => StorageOp::Delete,
+            0x0D => StorageOp::Read {
+                addr: d.read.get(),
+                length,
+            },
+            0x0F => {
+                let addr = d.write.get();
+                let append = d.append == 0x01;
+                let data = (0..length)
+                    .map(|i| vm.ram_read_byte(addr.wrapping_add(i)))
+                    .collect();
+                StorageOp::Write { data, append }
+            }
+            _ => return,
+        };
+
+        let name = Self::read_name(vm, name_addr);
+        self.pending.push(StorageRequest {
+            name,
+            per_instance,
+            op,
+        });
+    }
+
+    fn read_name(vm: &Uxn, addr: u16) -> String {
+        (0..MAX_NAME_LEN as u16)
+            .map(|i| vm.ram_read_byte(addr.wrapping_add(i)))
+            .take_while(|b| *b != 0)
+            .map(|b| b as char)
+            .collect()
+    }
+
+    /// Reports how many bytes the last request moved
+    pub fn complete(&mut self, vm: &mut Uxn, success: u16) {
+        vm.dev_mut::<StoragePorts>().success.set(success);
+    }
+}
@@ -0,0 +1,145 @@
+///! The Timer device exposes the simulation clock and lets programs schedule vectors.
+///!
+///! Reading `tickh`/`tickl` gives the current simulation tick. Writing 01 to `command`
+///! schedules the current `vector` to be called `delay` ticks from now in timer `slot`,
+///! repeating every `period` ticks if it's not zero. Writing 02 cancels the timer in `slot`.
+use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use zerocopy::{BigEndian, U16};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+pub const TIMER_SLOTS: usize = 4;
+
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct TimerPorts {
+    // |50 @Timer &vector $2 &tickh $2 &tickl $2 &delay $2 &period $2 &slot $1 &command $1
+    pub vector: U16<BigEndian>,
+    pub tickh: U16<BigEndian>,
+    pub tickl: U16<BigEndian>,
+    pub delay: U16<BigEndian>,
+    pub period: U16<BigEndian>,
+    pub slot: u8,
+    pub command: u8,
+    _p1: u32,
+}
+
+impl TimerPorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for TimerPorts {
+    const BASE: u8 = 0x50;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScheduledVector {
+    pub vector: u16,
+    pub fire_at: u64,
+    /// Ticks between repetitions, 0 for one-shot timers
+    pub period: u16,
+}
+
+pub struct Timer {
+    /// Current simulation tick, kept up to date by the timer system
+    pub now: u64,
+    pub slots: [Option<ScheduledVector>; TIMER_SLOTS],
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer {
+            now: 0,
+            slots: [None; TIMER_SLOTS],
+        }
+    }
+
+    pub fn dei(&mut self, vm: &mut Uxn, target: u8) {
+        if let 0x02..=0x05 = target & 0x0F {
+            let d = vm.dev_mut::<TimerPorts>();
+            d.tickh.set((self.now >> 16) as u16);
+            d.tickl.set(self.now as u16);
+        }
+    }
+
+    pub fn deo(&mut self, vm: &mut Uxn, target: u8) {
+        let d = vm.dev::<TimerPorts>();
+        if target & 0x0F != 0x0B {
+            return;
+        }
+
+        let slot = d.slot as usize % TIMER_SLOTS;
+        match d.command {
+            0x01 => {
+                self.slots[slot] = Some(ScheduledVector {
+                    vector: d.vector.get(),
+                    fire_at: self.now + d.delay.get().max(1) as u64,
+                    period: d.period.get(),
+                });
+            }
+            0x02 => {
+                self.slots[slot] = None;
+            }
+            _ => {
+                println!("UNKNOWN TIMER COMMAND");
+            }
+        }
+    }
+
+    /// Advances the clock to `now` and returns the vectors that became due, rescheduling
+    /// the repeating ones
+    pub fn advance(&mut self, now: u64) -> Vec<u16> {
+        self.now = now;
+        let mut due = Vec::new();
+
+        for slot in self.slots.iter_mut() {
+            if let Some(scheduled) = slot {
+                if scheduled.fire_at > now {
+                    continue;
+                }
+
+                due.push(scheduled.vector);
+                if scheduled.period == 0 {
+                    *slot = None;
+                } else {
+                    scheduled.fire_at = now + scheduled.period as u64;
+                }
+            }
+        }
+
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_shot_and_repeating_timers() {
+        let mut timer = Timer::new();
+        timer.slots[0] = Some(ScheduledVector {
+            vector: 0x0100,
+            fire_at: 2,
+            period: 0,
+        });
+        timer.slots[1] = Some(ScheduledVector {
+            vector: 0x0200,
+            fire_at: 1,
+            period: 2,
+        });
+
+        assert_eq!(timer.advance(1), vec![0x0200]);
+        assert_eq!(timer.advance(2), vec![0x0100]);
+        assert_eq!(timer.advance(3), vec![0x0200]);
+        assert_eq!(timer.advance(4), Vec::<u16>::new());
+        assert!(timer.slots[0].is_none());
+    }
+}
@@ -0,0 +1,118 @@
+///! The Weapon device lets a unit attack other units.
+///!
+///! Writing 01 to `command` fires at the unit whose handle is in `target`, writing 02
+///! fires at whatever unit is at `x`,`y`. After firing the weapon needs `cooldown` ticks
+///! before it can fire again. The outcome of the last shot is left in `status`.
+///!
+///! When the unit is hit, `attacker` is set to the handle of the shooter and `vector`
+///! is called.
+use raven_uxn::{Ports, Uxn, DEV_SIZE};
+use zerocopy::{BigEndian, U16};
+use zerocopy_derive::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+pub const DEFAULT_RANGE: u16 = 150;
+pub const DEFAULT_DAMAGE: u8 = 10;
+pub const DEFAULT_COOLDOWN: u8 = 30;
+
+pub const STATUS_OK: u8 = 0x00;
+pub const STATUS_COOLING_DOWN: u8 = 0x01;
+pub const STATUS_OUT_OF_RANGE: u8 = 0x02;
+pub const STATUS_NO_TARGET: u8 = 0x03;
+
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct WeaponPorts {
+    // |70 @Weapon &vector $2 &x $2 &y $2 &target $2 &range $2 &damage $1 &cooldown $1 &command $1 &status $1 &attacker $2
+    pub vector: U16<BigEndian>,
+    pub x: U16<BigEndian>,
+    pub y: U16<BigEndian>,
+    pub target: U16<BigEndian>,
+    pub range: U16<BigEndian>,
+    pub damage: u8,
+    pub cooldown: u8,
+    pub command: u8,
+    pub status: u8,
+    pub attacker: U16<BigEndian>,
+}
+
+impl WeaponPorts {
+    fn dev<'a>(vm: &'a Uxn, i: usize) -> &'a Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_at(pos)
+    }
+
+    fn dev_mut<'a>(vm: &'a mut Uxn, i: usize) -> &'a mut Self {
+        let pos = Self::BASE + (i * DEV_SIZE) as u8;
+        vm.dev_mut_at(pos)
+    }
+}
+
+impl Ports for WeaponPorts {
+    const BASE: u8 = 0x70;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShotTarget {
+    Handle(u16),
+    Coords(u16, u16),
+}
+
+pub struct Weapon {
+    pub range: u16,
+    pub damage: u8,
+    pub cooldown: u8,
+    /// Ticks until the weapon can fire again
+    pub cooldown_left: u8,
+    /// Shot requested by the program that has not been resolved yet
+    pub pending_shot: Option<ShotTarget>,
+}
+
+impl Weapon {
+    pub fn new() -> Self {
+        Weapon {
+            range: DEFAULT_RANGE,
+            damage: DEFAULT_DAMAGE,
+            cooldown: DEFAULT_COOLDOWN,
+            cooldown_left: 0,
+            pending_shot: None,
+        }
+    }
+
+    pub fn dei(&mut self, vm: &mut Uxn, target: u8) {
+        if let 0x08..=0x0B = target & 0x0F {
+            let d = vm.dev_mut::<WeaponPorts>();
+            d.range.set(self.range);
+            d.damage = self.damage;
+            d.cooldown = self.cooldown_left;
+        }
+    }
+
+    pub fn deo(&mut self, vm: &mut Uxn, target: u8) {
+        if target & 0x0F != 0x0C {
+            return;
+        }
+
+        let d = vm.dev_mut::<WeaponPorts>();
+        let shot = match d.command {
+            0x01 => ShotTarget::Handle(d.target.get()),
+            0x02 => ShotTarget::Coords(d.x.get(), d.y.get()),
+            _ => {
+                println!("UNKNOWN WEAPON COMMAND");
+                return;
+            }
+        };
+
+        if self.cooldown_left > 0 {
+            d.status = STATUS_COOLING_DOWN;
+            return;
+        }
+
+        self.cooldown_left = self.cooldown;
+        self.pending_shot = Some(shot);
+        d.status = STATUS_OK;
+    }
+
+    pub fn tick(&mut self) {
+        self.cooldown_left = self.cooldown_left.saturating_sub(1);
+    }
+}
@@ -0,0 +1,171 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::assets::AssetLibrary;
+use crate::components::{Depot, Executable, Inventory, ResourceNode, Selectable, Team};
+use crate::devices::cargo::{
+    CargoCommand, STATUS_EMPTY, STATUS_FULL, STATUS_NOTHING_IN_REACH, STATUS_OK,
+};
+use crate::executable::update_executables;
+use crate::spatial::SpatialIndex;
+
+/// Resources every team starts with
+pub const STARTING_RESOURCES: u32 = 500;
+/// How far a unit can reach to harvest from a node or deposit into a depot
+const CARGO_REACH: f32 = 40.0;
+/// How much a single harvest command takes from a node
+const HARVEST_AMOUNT: u16 = 10;
+
+const DEPOT_SIZE: Vec3 = Vec3::new(4., 4., 1.);
+
+/// Global resource counters for each team
+#[derive(Resource, Default)]
+pub struct TeamResources {
+    stock: HashMap<u8, u32>,
+}
+
+impl TeamResources {
+    pub fn get(&self, team: u8) -> u32 {
+        self.stock.get(&team).copied().unwrap_or(STARTING_RESOURCES)
+    }
+
+    pub fn add(&mut self, team: u8, amount: u32) {
+        let stock = self.get(team);
+        self.stock.insert(team, stock + amount);
+    }
+
+    pub fn set(&mut self, team: u8, amount: u32) {
+        self.stock.insert(team, amount);
+    }
+
+    /// Takes `amount` from the team if it has enough, returns whether it did
+    pub fn try_spend(&mut self, team: u8, amount: u32) -> bool {
+        let stock = self.get(team);
+        if stock < amount {
+            return false;
+        }
+        self.stock.insert(team, stock - amount);
+        true
+    }
+
+    /// Teams whose stock has changed since the start, ordered by team
+    pub fn teams(&self) -> Vec<(u8, u32)> {
+        let mut teams: Vec<(u8, u32)> = self.stock.iter().map(|(t, s)| (*t, *s)).collect();
+        teams.sort();
+        teams
+    }
+}
+
+#[derive(Event)]
+pub struct SpawnDepotRequest {
+    pub team: u8,
+    pub position: Vec2,
+}
+
+fn spawn_depots(
+    mut requests: EventReader<SpawnDepotRequest>,
+    mut commands: Commands,
+    asset_lib: Res<AssetLibrary>,
+) {
+    for request in requests.read() {
+        let asset = &asset_lib.assets["purple"];
+
+        commands.spawn((
+            Sprite::from_atlas_image(
+                asset.image.clone(),
+                TextureAtlas {
+                    layout: asset.layout.clone(),
+                    index: asset.mappings["building-t"],
+                },
+            ),
+            Transform {
+                translation: request.position.extend(0.),
+                scale: DEPOT_SIZE,
+                ..default()
+            },
+            Depot,
+            Team(request.team),
+            Selectable::building(),
+        ));
+    }
+}
+
+fn resolve_cargo(
+    mut commands: Commands,
+    mut units: Query<(&mut Executable, &Transform, &Team, &mut Inventory)>,
+    mut nodes: Query<&mut ResourceNode>,
+    depots: Query<&Team, With<Depot>>,
+    mut resources: ResMut<TeamResources>,
+    index: Res<SpatialIndex>,
+) {
+    for (mut executable, transform, team, mut inventory) in &mut units {
+        let Some(command) = executable.device.cargo.pending_command.take() else {
+            continue;
+        };
+
+        let nearby = index.query_radius(transform.translation.xy(), CARGO_REACH);
+        // Nodes emptied earlier this frame are still around until the despawn is applied
+        let closest_node = nearby
+            .iter()
+            .map(|(entity, _)| *entity)
+            .find(|entity| nodes.get(*entity).is_ok_and(|node| node.amount > 0));
+        let closest_depot = nearby
+            .iter()
+            .map(|(entity, _)| *entity)
+            .find(|entity| depots.get(*entity).is_ok_and(|depot_team| depot_team == team));
+
+        let mut node_left = 0;
+        let status = match (command, closest_node, closest_depot) {
+            (CargoCommand::Harvest, None, _) | (CargoCommand::Query, None, _) => {
+                STATUS_NOTHING_IN_REACH
+            }
+            (CargoCommand::Harvest, Some(_), _) if inventory.space_left() == 0 => STATUS_FULL,
+            (CargoCommand::Harvest, Some(node_entity), _) => {
+                let mut node = nodes.get_mut(node_entity).unwrap();
+                let taken = HARVEST_AMOUNT
+                    .min(inventory.space_left())
+                    .min(node.amount);
+                node.amount -= taken;
+                inventory.carried += taken;
+                node_left = node.amount;
+
+                if node.amount == 0 {
+                    commands.entity(node_entity).despawn();
+                }
+                STATUS_OK
+            }
+            (CargoCommand::Query, Some(node_entity), _) => {
+                node_left = nodes.get(node_entity).unwrap().amount;
+                STATUS_OK
+            }
+            (CargoCommand::Deposit, _, None) => STATUS_NOTHING_IN_REACH,
+            (CargoCommand::Deposit, _, Some(_)) if inventory.carried == 0 => STATUS_EMPTY,
+            (CargoCommand::Deposit, _, Some(_)) => {
+                resources.add(team.0, inventory.carried as u32);
+                inventory.carried = 0;
+                STATUS_OK
+            }
+        };
+
+        let Executable { cpu, device, .. } = &mut *executable;
+        device
+            .cargo
+            .complete(cpu, inventory.carried, inventory.capacity, node_left, status);
+
+        let cargo_vec = executable.cargo_vector();
+        if cargo_vec != 0 {
+            executable.vector_queue.push(cargo_vec);
+        }
+    }
+}
+
+pub struct EconomyPlugin;
+
+impl Plugin for EconomyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TeamResources>()
+            .add_event::<SpawnDepotRequest>()
+            .add_systems(Update, resolve_cargo.after(update_executables))
+            .add_systems(PostUpdate, spawn_depots);
+    }
+}
@@ -1,38 +1,84 @@
 use bevy::prelude::*;
 
-use crate::components::Executable;
+use crate::components::executable::ReloadReport;
+use crate::components::{Executable, MaxSpeed};
+use crate::devices::movement::MovementPorts;
 use crate::radio::RadioMessage;
 use crate::tools::assembler::Program;
 
+/// How live units pick up new code
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ReloadMode {
+    /// Start over from the reset vector with fresh memory
+    #[default]
+    Reset,
+    /// Keep the memory of labels that didn't change, see `Executable::reload_preserving_state`
+    PreserveState,
+}
+
 #[derive(Event, Debug)]
 pub struct CodeReloadEvent {
     pub program: Program,
     pub unit_id: u64,
+    /// Saved version the program was assembled from, None for unsaved code
+    pub version_id: Option<u64>,
+    pub mode: ReloadMode,
 }
 
-fn update_executables(
-    mut query: Query<(Entity, &mut Executable, &mut Transform)>,
+/// Sent after live units picked up new code, with what happened to their memory
+#[derive(Event, Clone, Debug)]
+pub struct CodeReloaded {
+    pub unit_id: u64,
+    /// Number of live units that were reloaded
+    pub units: usize,
+    /// Labels migrated in every unit, and the ones reset in any of them
+    pub report: ReloadReport,
+}
+
+pub fn update_executables(
+    mut query: Query<(Entity, &mut Executable, &mut Transform, Option<&MaxSpeed>)>,
     mut radio_messages: EventWriter<RadioMessage>,
 ) {
-    for (entity, mut executable, mut transform) in &mut query {
-        executable.cycles_left = 1000;
+    for (entity, mut executable, mut transform, max_speed) in &mut query {
+        executable.cycles_left = executable.limits.num_cycles;
+        let speed = max_speed.copied().unwrap_or_default().0;
+
+        // Without a Movement device the ports are just memory, the unit stays where it is
+        if executable.device.installed.is_installed(MovementPorts::BASE) {
+            let pos = transform.translation;
+            let target_pos = executable.target_pos();
+            let dir = (target_pos - pos).normalize();
 
-        let pos = transform.translation;
-        let target_pos = executable.target_pos();
-        let dir = (target_pos - pos).normalize();
+            if transform.translation.distance(target_pos) < speed {
+                transform.translation = target_pos;
+            } else {
+                transform.translation += dir * speed;
+            }
 
-        if transform.translation.distance(target_pos) < 10.0 {
-            transform.translation = target_pos;
-        } else {
-            transform.translation += dir * 10.0;
+            executable.set_current_pos(transform.translation);
         }
 
-        executable.set_current_pos(transform.translation);
+        if executable.out_of_cycles {
+            for mut rm in executable.cont(&mut transform) {
+                rm.origin_entity_id = Some(entity);
+                radio_messages.send(rm);
+            }
+        }
+
+        // Vectors queued by devices (e.g. timers) run before the loop vector
+        while executable.pc.is_none() && !executable.vector_queue.is_empty() {
+            let vector = executable.vector_queue.remove(0);
+            executable.pc = Some(vector);
+            for mut rm in executable.cont(&mut transform) {
+                rm.origin_entity_id = Some(entity);
+                radio_messages.send(rm);
+            }
+        }
 
         if let None = executable.pc {
             let loop_vec = executable.loop_vector();
             executable.pc = Some(loop_vec);
-            if let Some(mut rm) = executable.cont(&mut transform) {
+            for mut rm in executable.cont(&mut transform) {
                 rm.origin_entity_id = Some(entity);
                 radio_messages.send(rm);
             }
@@ -43,13 +89,45 @@ fn update_executables(
 fn code_reload_event_handler(
     mut reader: EventReader<CodeReloadEvent>,
     mut query: Query<(&mut Executable, &mut Transform)>,
+    mut reloaded: EventWriter<CodeReloaded>,
 ) {
     for ev in reader.read() {
+        let mut units = 0;
+        let mut report: Option<ReloadReport> = None;
+
         for (mut executable, mut transform) in &mut query {
-            if executable.unit_id == ev.unit_id {
-                executable.load_program(&ev.program, &mut transform);
+            if executable.unit_type_id != ev.unit_id {
+                continue;
             }
+            units += 1;
+
+            match ev.mode {
+                ReloadMode::Reset => executable.load_program(&ev.program, &mut transform),
+                ReloadMode::PreserveState => {
+                    let unit_report = executable.reload_preserving_state(&ev.program, &mut transform);
+                    // Units may have been running different versions, so they can disagree
+                    report = Some(match report {
+                        None => unit_report,
+                        Some(mut merged) => {
+                            merged.migrated.retain(|name| unit_report.migrated.contains(name));
+                            for name in unit_report.reset {
+                                if !merged.reset.contains(&name) {
+                                    merged.reset.push(name);
+                                }
+                            }
+                            merged
+                        }
+                    });
+                }
+            }
+            executable.version_id = ev.version_id;
         }
+
+        reloaded.send(CodeReloaded {
+            unit_id: ev.unit_id,
+            units,
+            report: report.unwrap_or_default(),
+        });
     }
 }
 
@@ -57,9 +135,38 @@ pub struct ExecutablePlugin;
 
 impl Plugin for ExecutablePlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<CodeReloadEvent>().add_systems(
+        app.add_event::<CodeReloadEvent>()
+            .add_event::<CodeReloaded>()
+            .add_systems(
             Update,
             (update_executables, code_reload_event_handler).chain(),
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::InstalledDevices;
+    use crate::tools::assembler::assemble;
+
+    #[test]
+    fn test_unit_without_movement_stays_put() {
+        let program = assemble("|100 BRK".to_string()).unwrap();
+        let executable = Executable::with_devices(1, &program, InstalledDevices(0));
+        let start = Vec3::new(100., 50., 0.);
+
+        let mut app = App::new();
+        app.add_event::<RadioMessage>();
+        app.add_systems(Update, update_executables);
+        let unit = app
+            .world_mut()
+            .spawn((executable, Transform::from_translation(start), MaxSpeed(10.)))
+            .id();
+
+        app.update();
+        app.update();
+
+        assert_eq!(app.world().get::<Transform>(unit).unwrap().translation, start);
+    }
+}
@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+
+use crate::components::{Executable, Team};
+use crate::devices::factory::{
+    BUILD_COST, STATUS_NO_RESOURCES, STATUS_SPAWN_FAILED, STATUS_UNKNOWN_TYPE,
+};
+use crate::economy::TeamResources;
+use crate::executable::update_executables;
+use crate::unit_repo::{RepoError, UnitRepository};
+use crate::unit_spawn::{SpawnUnitRequest, UnitSpawnFailed};
+
+fn run_factories(
+    mut query: Query<(Entity, &mut Executable, &Transform, &Team)>,
+    mut spawn_events: EventWriter<SpawnUnitRequest>,
+    mut resources: ResMut<TeamResources>,
+    repo: Res<UnitRepository>,
+    mut lookup_failing: Local<bool>,
+) {
+    for (entity, mut executable, transform, team) in &mut query {
+        let Executable { cpu, device, .. } = &mut *executable;
+        let factory = &mut device.factory;
+
+        if std::mem::take(&mut factory.refund_due) {
+            resources.add(team.0, BUILD_COST);
+        }
+
+        if let Some(order) = factory.pending_order.take() {
+            // Same lookup the spawn does, so a paid for build only fails if the code
+            // changes while it's underway
+            let program = repo.get_current_program(order.unit_type as u64);
+            if program.is_ok() {
+                *lookup_failing = false;
+            }
+            match program {
+                Err(
+                    RepoError::UnitNotFound(_)
+                    | RepoError::NoCode(_)
+                    | RepoError::BrokenVersion { .. },
+                ) => {
+                    factory.reject(cpu, STATUS_UNKNOWN_TYPE);
+                }
+                Err(e) => {
+                    // Not the program's fault, keep the order and try again next tick, only
+                    // reporting the first failure in a row
+                    if !std::mem::replace(&mut *lookup_failing, true) {
+                        eprintln!("Factory couldn't look up unit type {}: {}", order.unit_type, e);
+                    }
+                    factory.pending_order = Some(order);
+                }
+                Ok(_) if !resources.try_spend(team.0, BUILD_COST) => {
+                    factory.reject(cpu, STATUS_NO_RESOURCES);
+                }
+                Ok(_) => factory.start(cpu, order),
+            }
+        }
+
+        if let Some(order) = factory.tick(cpu) {
+            let offset = Vec2::new(order.dx as f32, order.dy as f32);
+            spawn_events.send(SpawnUnitRequest {
+                unit_id: order.unit_type as u64,
+                team: team.0,
+                position: transform.translation.xy() + offset,
+                producer: Some(entity),
+            });
+        }
+    }
+}
+
+/// Gives the cost of builds that couldn't be spawned back and tells the factory's program
+fn refund_failed_builds(
+    mut failures: EventReader<UnitSpawnFailed>,
+    mut producers: Query<(&mut Executable, &Team)>,
+    mut resources: ResMut<TeamResources>,
+) {
+    for failure in failures.read() {
+        let Some(Ok((mut producer, team))) = failure.producer.map(|p| producers.get_mut(p)) else {
+            continue;
+        };
+        resources.add(team.0, BUILD_COST);
+
+        let Executable { cpu, device, .. } = &mut *producer;
+        device.factory.set_child(cpu, 0);
+        device.factory.reject(cpu, STATUS_SPAWN_FAILED);
+
+        let create_vec = producer.create_vector();
+        if create_vec != 0 {
+            producer.vector_queue.push(create_vec);
+        }
+    }
+}
+
+pub struct FactoryPlugin;
+
+impl Plugin for FactoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (refund_failed_builds, run_factories)
+                .chain()
+                .after(update_executables),
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use bevy::prelude::*;
+
+use crate::components::{Executable, Health};
+use crate::executable::update_executables;
+
+fn sync_health(mut query: Query<(&mut Executable, &Health), Changed<Health>>) {
+    for (mut executable, health) in &mut query {
+        let identity = &mut executable.device.identity;
+        identity.hp = health.hp;
+        identity.max_hp = health.max_hp;
+    }
+}
+
+pub struct IdentityPlugin;
+
+impl Plugin for IdentityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, sync_health.before(update_executables));
+    }
+}
@@ -12,23 +12,55 @@ use egui::{
 };
 
 mod bundles;
+mod cli;
+mod combat;
 mod components;
+mod console;
 mod devices;
+mod economy;
 mod executable;
+mod factory;
+mod identity;
 mod radio;
+mod radio_inspector;
+mod random;
 mod sandbox;
+mod sensor;
+mod spatial;
+mod storage;
+mod timer;
 mod tools;
+mod unit_export;
 mod unit_repo;
 mod unit_spawn;
+mod workspace;
+mod world_save;
 mod assets;
 
-use crate::components::{Executable, Selectable, Selected};
-use crate::devices::{CommandPorts, MovementPorts, RadioPorts};
+use crate::combat::CombatPlugin;
+use crate::components::{Executable, Health, InstanceId, Inventory, Selectable, Selected, Team};
+use crate::console::ConsolePlugin;
+use crate::devices::{
+    CargoPorts, CommandPorts, FactoryPorts, MovementPorts, RadioLinkPorts, RadioPorts, SensorPorts, TimerPorts,
+    WeaponPorts,
+};
+use crate::devices::interface::WidgetKind;
+use crate::economy::EconomyPlugin;
 use crate::executable::ExecutablePlugin;
+use crate::factory::FactoryPlugin;
+use crate::identity::IdentityPlugin;
 use crate::radio::{RadioMessage, RadioPlugin};
+use crate::radio_inspector::RadioInspectorPlugin;
+use crate::random::RandomPlugin;
 use crate::sandbox::SandboxPlugin;
+use crate::sensor::SensorPlugin;
+use crate::spatial::SpatialPlugin;
+use crate::storage::StoragePlugin;
+use crate::timer::TimerPlugin;
 use crate::tools::assembler::{disassm, DisassmAtom};
 use crate::unit_repo::UnitRepoPlugin;
+use crate::workspace::Workspaces;
+use crate::world_save::WorldSavePlugin;
 use crate::unit_spawn::UnitSpawnPlugin;
 use crate::assets::AssetsPlugin;
 
@@ -105,7 +137,8 @@ fn gizmos(
 
 fn command_system(
     mut context: EguiContexts,
-    mut query: Query<(Entity, &mut Executable, &mut Transform), With<Selected>>,
+    mut query: Query<(Entity, &mut Executable, &mut Transform, &Team), With<Selected>>,
+    targets: Query<(&InstanceId, &Team, &Transform), Without<Selected>>,
     mut mouse_events: EventReader<MouseButtonInput>,
     mut radio_messages: EventWriter<RadioMessage>,
     q_window: Query<&Window, With<bevy::window::PrimaryWindow>>,
@@ -123,19 +156,41 @@ fn command_system(
         .cursor_position()
         .map(|cursor| camera.viewport_to_world_2d(global_transform, cursor))
     {
+        let wp_aabb = Aabb2d::new(world_position, Vec2::new(0., 0.));
+        let under_cursor: Vec<(InstanceId, Team)> = targets
+            .iter()
+            .filter(|(_, _, transform)| {
+                Aabb2d::new(transform.translation.xy(), transform.scale.xy() * 9.)
+                    .contains(&wp_aabb)
+            })
+            .map(|(instance_id, team, _)| (*instance_id, *team))
+            .collect();
+
         for event in mouse_events.read() {
             query
                 .iter_mut()
-                .for_each(|(eid, mut executable, mut transform)| {
+                .for_each(|(eid, mut executable, mut transform, team)| {
                     match (event.button, event.state) {
                         (MouseButton::Right, ButtonState::Released) => {
-                            let move_vec = executable.move_vector();
-                            executable.set_move_command_coords(
-                                world_position.x as u16,
-                                world_position.y as u16,
-                            );
-                            executable.pc = Some(move_vec);
-                            if let Some(mut rm) = executable.cont(&mut transform) {
+                            let enemy = under_cursor.iter().find(|(_, other)| other != team);
+
+                            if let Some((enemy_id, _)) = enemy {
+                                let attack_vec = executable.attack_vector();
+                                executable.set_attack_command(
+                                    world_position.x as u16,
+                                    world_position.y as u16,
+                                    enemy_id.0,
+                                );
+                                executable.pc = Some(attack_vec);
+                            } else {
+                                let move_vec = executable.move_vector();
+                                executable.set_move_command_coords(
+                                    world_position.x as u16,
+                                    world_position.y as u16,
+                                );
+                                executable.pc = Some(move_vec);
+                            }
+                            for mut rm in executable.cont(&mut transform) {
                                 rm.origin_entity_id = Some(eid);
                                 radio_messages.send(rm);
                             }
@@ -175,11 +230,47 @@ fn format_disassm_atom(atom: &DisassmAtom, is_current_instr: bool) -> WidgetText
 
 fn executable_debugging(
     mut context: EguiContexts,
-    mut executables: Query<(Entity, &mut Executable, &mut Transform), With<Selected>>,
+    mut executables: Query<(Entity, &mut Executable, &mut Transform, &InstanceId, &Health, &Inventory), With<Selected>>,
 ) {
-    executables.iter_mut().for_each(|(_eid, mut executable, mut transform)| {
+    executables.iter_mut().for_each(|(_eid, mut executable, mut transform, instance_id, health, inventory)| {
         egui::Window::new("Unit Inspector".to_string()).scroll(true).show(context.ctx_mut(), |ui| {
-            ui.label(format!("Unit Type ID: {}", executable.unit_id));
+            ui.label(format!("Unit Type ID: {}", executable.unit_type_id));
+            match executable.version_id {
+                Some(version_id) => ui.label(format!("Version: #{}", version_id)),
+                None => ui.label("Version: unsaved"),
+            };
+            ui.label(format!("Handle: {:04X}", instance_id.0));
+            ui.label(format!("Spawned at tick: {}", executable.device.identity.spawn_tick));
+            ui.label(format!("HP: {}/{} (armor {})", health.hp, health.max_hp, health.armor));
+            ui.label(format!("Cargo: {}/{}", inventory.carried, inventory.capacity));
+
+            if !executable.device.interface.widgets.is_empty() {
+                egui::CollapsingHeader::new("Interface").default_open(true).show(ui, |ui| {
+                    let mut clicked = None;
+
+                    for (id, widget) in &executable.device.interface.widgets {
+                        match widget.kind {
+                            WidgetKind::Label => {
+                                ui.label(&widget.text);
+                            }
+                            WidgetKind::Button => {
+                                if ui.button(&widget.text).clicked() {
+                                    clicked = Some(*id);
+                                }
+                            }
+                            WidgetKind::Toggle => {
+                                if ui.selectable_label(widget.value != 0, &widget.text).clicked() {
+                                    clicked = Some(*id);
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(id) = clicked {
+                        executable.click_widget(id);
+                    }
+                });
+            }
             egui::CollapsingHeader::new("Devices").show(ui, |ui| {
                 egui::CollapsingHeader::new("Command").show(ui, |ui| {
                     let cmd = executable.cpu.dev::<CommandPorts>();
@@ -189,6 +280,7 @@ fn executable_debugging(
                     ui.label(format!("x: {:02X}", cmd.x.get()));
                     ui.label(format!("y: {:02X}", cmd.y.get()));
                     ui.label(format!("Loop Vector: {:02X}", cmd.loop_vector.get()));
+                    ui.label(format!("target: {:04X}", cmd.target.get()));
                 });
 
                 egui::CollapsingHeader::new("Movement").show(ui, |ui| {
@@ -209,9 +301,127 @@ fn executable_debugging(
                     ui.label(format!("freq: {:02X}", cmd.freq));
                     ui.label(format!("strength: {:02X}", cmd.strength));
                     ui.label(format!("enabled: {:02X}", cmd.enabled));
+                    ui.label(format!("rssi: {:02X}", cmd.rssi));
+                    let link = executable.cpu.dev::<RadioLinkPorts>();
+                    ui.label(format!("addr: {:04X}", link.addr.get()));
+                    ui.label(format!("length: {:02X}", link.length));
+                    ui.label(format!("peer: {:04X}", link.peer.get()));
+                    ui.label(format!("sender: {:04X}", link.sender.get()));
+                    ui.label(format!("rx-addr: {:04X}", link.rx_addr.get()));
+                    ui.label(format!("rx-max: {:02X}", link.rx_max));
+                    ui.label(format!("rx-length: {:02X}", link.rx_length));
+                    ui.label(format!("rx-freq: {:02X}", link.rx_freq));
+                    let radio = &executable.device.radio;
+                    if radio.scanning {
+                        ui.label("listening on every frequency");
+                    } else if !radio.extra_frequencies.is_empty() {
+                        let freqs: Vec<String> = radio
+                            .extra_frequencies
+                            .iter()
+                            .map(|f| format!("{:02X}", f))
+                            .collect();
+                        ui.label(format!("also listening on: {}", freqs.join(" ")));
+                    }
+                    ui.label(format!("inbox: {}", radio.inbox.len()));
+                    ui.label(format!(
+                        "sent: {} received: {} dropped: {}",
+                        radio.sent, radio.received, radio.dropped
+                    ));
+                });
+
+                egui::CollapsingHeader::new("Sensor").show(ui, |ui| {
+                    let cmd = executable.cpu.dev::<SensorPorts>();
+                    ui.label(format!("Vector: {:04X}", cmd.vector.get()));
+                    ui.label(format!("radius: {:04X}", cmd.radius.get()));
+                    ui.label(format!("count: {:02X}", cmd.count));
+                    ui.label(format!("index: {:02X}", cmd.index));
+                    ui.label(format!("dx: {:04X}", cmd.dx.get()));
+                    ui.label(format!("dy: {:04X}", cmd.dy.get()));
+                    ui.label(format!("type: {:04X}", cmd.unit_type.get()));
+                    ui.label(format!("kind: {:02X}", cmd.kind));
+                    ui.label(format!("friend: {:02X}", cmd.friend));
+                });
+
+                egui::CollapsingHeader::new("Timer").show(ui, |ui| {
+                    ui.label(format!("tick: {}", executable.device.timer.now));
+                    for (slot, scheduled) in executable.device.timer.slots.iter().enumerate() {
+                        match scheduled {
+                            Some(s) => ui.label(format!(
+                                "slot {}: {:04X} at {} every {}",
+                                slot, s.vector, s.fire_at, s.period
+                            )),
+                            None => ui.label(format!("slot {}: -", slot)),
+                        };
+                    }
+                    let cmd = executable.cpu.dev::<TimerPorts>();
+                    ui.label(format!("delay: {:04X}", cmd.delay.get()));
+                    ui.label(format!("period: {:04X}", cmd.period.get()));
+                });
+
+                egui::CollapsingHeader::new("Weapon").show(ui, |ui| {
+                    let weapon = &executable.device.weapon;
+                    ui.label(format!("range: {}", weapon.range));
+                    ui.label(format!("damage: {}", weapon.damage));
+                    ui.label(format!("cooldown: {}/{}", weapon.cooldown_left, weapon.cooldown));
+                    let cmd = executable.cpu.dev::<WeaponPorts>();
+                    ui.label(format!("Hit Vector: {:04X}", cmd.vector.get()));
+                    ui.label(format!("target: {:04X}", cmd.target.get()));
+                    ui.label(format!("status: {:02X}", cmd.status));
+                    ui.label(format!("attacker: {:04X}", cmd.attacker.get()));
+                });
+
+                egui::CollapsingHeader::new("Factory").show(ui, |ui| {
+                    if let Some(job) = executable.device.factory.job {
+                        ui.label(format!(
+                            "building type {} ({} ticks left)",
+                            job.order.unit_type, job.ticks_left
+                        ));
+                    }
+                    let cmd = executable.cpu.dev::<FactoryPorts>();
+                    ui.label(format!("type: {:04X}", cmd.unit_type.get()));
+                    ui.label(format!("dx: {:04X}", cmd.dx.get()));
+                    ui.label(format!("dy: {:04X}", cmd.dy.get()));
+                    ui.label(format!("child: {:04X}", cmd.child.get()));
+                    ui.label(format!("progress: {:02X}", cmd.progress));
+                    ui.label(format!("status: {:02X}", cmd.status));
+                });
+
+                egui::CollapsingHeader::new("Cargo").show(ui, |ui| {
+                    let cmd = executable.cpu.dev::<CargoPorts>();
+                    ui.label(format!("Vector: {:04X}", cmd.vector.get()));
+                    ui.label(format!("carried: {:04X}", cmd.carried.get()));
+                    ui.label(format!("capacity: {:04X}", cmd.capacity.get()));
+                    ui.label(format!("node: {:04X}", cmd.node.get()));
+                    ui.label(format!("command: {:02X}", cmd.command));
+                    ui.label(format!("status: {:02X}", cmd.status));
                 });
             });
 
+            egui::CollapsingHeader::new("Console").default_open(true).show(ui, |ui| {
+                let console = &executable.device.console;
+                egui::ScrollArea::vertical()
+                    .max_height(150.)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in &console.lines {
+                            let color = if line.is_error { Color32::RED } else { Color32::WHITE };
+                            ui.label(RichText::new(&line.text).color(color).monospace());
+                        }
+
+                        let (out, err) = console.pending_text();
+                        if !out.is_empty() {
+                            ui.label(RichText::new(out).color(Color32::WHITE).monospace());
+                        }
+                        if !err.is_empty() {
+                            ui.label(RichText::new(err).color(Color32::RED).monospace());
+                        }
+                    });
+
+                if ui.button("Clear").clicked() {
+                    executable.device.console.clear();
+                }
+            });
+
             egui::CollapsingHeader::new("Disassembly").show(ui, |ui| {
                 let disassm = disassm(&executable.program);
                 let mut instr_docs: Option<String> = None;
@@ -404,7 +614,14 @@ fn configure_visuals_system(mut contexts: EguiContexts) {
 struct Unit {}
 
 fn main() -> Result<()> {
+    let mut args: Vec<String> = std::env::args().collect();
+    let workspaces = Workspaces::from_args(&mut args);
+    if let Some(result) = cli::run(&args, &workspaces) {
+        return result;
+    }
+
     App::new()
+        .insert_resource(workspaces)
         .add_plugins(UnitRepoPlugin)
         .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
         .add_plugins(HelloPlugin)
@@ -413,6 +630,18 @@ fn main() -> Result<()> {
         .add_plugins(SandboxPlugin)
         .add_plugins(ExecutablePlugin)
         .add_plugins(RadioPlugin)
+        .add_plugins(RadioInspectorPlugin)
+        .add_plugins(WorldSavePlugin)
+        .add_plugins(SpatialPlugin)
+        .add_plugins(SensorPlugin)
+        .add_plugins(ConsolePlugin)
+        .add_plugins(TimerPlugin)
+        .add_plugins(RandomPlugin)
+        .add_plugins(CombatPlugin)
+        .add_plugins(EconomyPlugin)
+        .add_plugins(FactoryPlugin)
+        .add_plugins(IdentityPlugin)
+        .add_plugins(StoragePlugin)
         .add_plugins(AssetsPlugin)
         .insert_resource(ClearColor(BACKGROUND_COLOR))
         .run();
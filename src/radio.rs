@@ -1,30 +1,267 @@
-use crate::components::Executable;
-use bevy::prelude::{App, Entity, Event, EventReader, Plugin, Query, Transform, Update};
+use std::collections::BTreeMap;
 
-#[derive(Event, Copy, Clone, Debug)]
+use crate::components::{Executable, InstanceId};
+use crate::devices::radio::{range_for_strength, received_strength, ReceivedPacket};
+use crate::devices::Random;
+use crate::random::WorldSeed;
+use crate::spatial::SpatialIndex;
+use crate::timer::SimulationTick;
+use bevy::prelude::{
+    App, Entity, Event, EventReader, EventWriter, FromWorld, IntoSystemConfigs, Plugin, PostUpdate, Query, Res,
+    ResMut, Resource, Transform, Vec2, World,
+};
+
+#[derive(Event, Clone, Debug)]
 pub struct RadioMessage {
     pub origin_entity_id: Option<Entity>,
-    pub packets: [u16; 2],
+    pub origin_position: Vec2,
+    pub payload: Vec<u8>,
     pub frequency: u8,
+    pub strength: u8,
+    /// Handle of the only unit meant to receive the message, broadcast to everyone if None
+    pub destination: Option<u16>,
+    /// Number of reception handlers this message went through before being sent, messages
+    /// with more than `MAX_HOPS` are dropped so relays can't bounce packets around forever
+    pub hops: u8,
 }
 
-fn route_radio_messages(
-    mut query: Query<(Entity, &mut Executable, &mut Transform)>,
+impl RadioMessage {
+    pub fn new(payload: Vec<u8>, frequency: u8, strength: u8, destination: Option<u16>) -> Self {
+        RadioMessage {
+            origin_entity_id: None,
+            origin_position: Vec2::ZERO,
+            payload,
+            frequency,
+            strength,
+            destination,
+            hops: 0,
+        }
+    }
+}
+
+/// Record of a message that went out on the network, for inspecting traffic
+#[derive(Event, Clone, Debug)]
+pub struct RadioTransmission {
+    pub tick: u64,
+    pub sender: Option<Entity>,
+    /// Handle of the sending unit, 0 if it didn't come from a unit
+    pub sender_handle: u16,
+    pub origin_position: Vec2,
+    pub range: f32,
+    pub frequency: u8,
+    pub destination: Option<u16>,
+    pub payload: Vec<u8>,
+    pub hops: u8,
+    /// Radios the message is on its way to, after collisions and losses
+    pub receivers: Vec<Entity>,
+}
+
+pub const MAX_HOPS: u8 = 8;
+/// Most messages the network carries in a single tick, anything over it is dropped
+pub const MAX_TRANSMISSIONS_PER_TICK: usize = 1024;
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct RadioNetworkConfig {
+    /// Ticks between a packet being sent and it arriving
+    pub latency_ticks: u64,
+    /// Chance, between 0 and 1, of any single packet getting lost on the way
+    pub loss_probability: f32,
+}
+
+impl Default for RadioNetworkConfig {
+    fn default() -> Self {
+        RadioNetworkConfig {
+            latency_ticks: 1,
+            loss_probability: 0.0,
+        }
+    }
+}
+
+struct InFlightPacket {
+    receiver: Entity,
+    deliver_at: u64,
+    packet: ReceivedPacket,
+}
+
+/// Packets on their way to their receivers
+#[derive(Resource)]
+pub struct RadioNetwork {
+    in_flight: Vec<InFlightPacket>,
+    /// Decides which packets get lost, seeded from the world so losses are reproducible
+    rng: Random,
+}
+
+impl FromWorld for RadioNetwork {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world
+            .get_resource::<WorldSeed>()
+            .copied()
+            .unwrap_or_else(WorldSeed::from_env);
+        let mut rng = Random::new();
+        rng.reseed(seed.0, u64::MAX);
+
+        RadioNetwork {
+            in_flight: Vec::new(),
+            rng,
+        }
+    }
+}
+
+impl RadioNetwork {
+    fn is_lost(&mut self, loss_probability: f32) -> bool {
+        let roll = (self.rng.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        roll < loss_probability
+    }
+}
+
+pub fn transmit_radio_messages(
+    mut query: Query<(&mut Executable, &InstanceId)>,
     mut in_radio_messages: EventReader<RadioMessage>,
+    mut network: ResMut<RadioNetwork>,
+    config: Res<RadioNetworkConfig>,
+    tick: Res<SimulationTick>,
+    index: Res<SpatialIndex>,
+    mut transmission_events: EventWriter<RadioTransmission>,
+) {
+    // Every signal reaching each receiver this tick, by frequency, along with its transmitter
+    // and the transmission it belongs to
+    let mut arrivals: BTreeMap<(Entity, u8), Vec<(Option<Entity>, usize, ReceivedPacket)>> =
+        BTreeMap::new();
+    let mut transmissions = Vec::new();
+
+    for (i, msg) in in_radio_messages.read().enumerate() {
+        let mut sender_handle = 0;
+        if let Some(Ok((mut sender, instance_id))) = msg.origin_entity_id.map(|e| query.get_mut(e)) {
+            if i >= MAX_TRANSMISSIONS_PER_TICK {
+                sender.device.radio.dropped += 1;
+                continue;
+            }
+            sender.device.radio.sent += 1;
+            sender_handle = instance_id.0;
+        } else if i >= MAX_TRANSMISSIONS_PER_TICK {
+            continue;
+        }
+
+        let range = range_for_strength(msg.strength);
+        let transmission = transmissions.len();
+        transmissions.push(RadioTransmission {
+            tick: tick.0,
+            sender: msg.origin_entity_id,
+            sender_handle,
+            origin_position: msg.origin_position,
+            range,
+            frequency: msg.frequency,
+            destination: msg.destination,
+            payload: msg.payload.clone(),
+            hops: msg.hops,
+            receivers: Vec::new(),
+        });
+
+        for (entity, pos) in index.query_radius(msg.origin_position, range) {
+            if msg.origin_entity_id == Some(entity) {
+                continue;
+            }
+
+            let Ok((mut executable, instance_id)) = query.get_mut(entity) else {
+                continue;
+            };
+
+            if msg.destination.is_some_and(|handle| handle != instance_id.0) {
+                continue;
+            }
+
+            if !executable.radio_enabled() || !executable.radio_listens_on(msg.frequency) {
+                continue;
+            }
+
+            arrivals
+                .entry((entity, msg.frequency))
+                .or_default()
+                .push((
+                    msg.origin_entity_id,
+                    transmission,
+                    ReceivedPacket {
+                        payload: msg.payload.clone(),
+                        frequency: msg.frequency,
+                        sender: sender_handle,
+                        rssi: received_strength(pos.distance(msg.origin_position), range),
+                        hops: msg.hops,
+                    },
+                ));
+        }
+    }
+
+    for ((receiver, _), packets) in arrivals {
+        let Ok((mut executable, _)) = query.get_mut(receiver) else {
+            continue;
+        };
+
+        // Overlapping transmissions from different units on the same frequency garble
+        // each other, several packets from a single unit go through one after another
+        let first_transmitter = packets[0].0;
+        if packets.iter().any(|(transmitter, _, _)| *transmitter != first_transmitter) {
+            executable.device.radio.dropped += packets.len() as u32;
+            continue;
+        }
+
+        for (_, transmission, packet) in packets {
+            if network.is_lost(config.loss_probability) {
+                executable.device.radio.dropped += 1;
+                continue;
+            }
+
+            transmissions[transmission].receivers.push(receiver);
+            network.in_flight.push(InFlightPacket {
+                receiver,
+                deliver_at: tick.0 + config.latency_ticks,
+                packet,
+            });
+        }
+    }
+
+    transmission_events.send_batch(transmissions);
+}
+
+fn deliver_radio_messages(
+    mut query: Query<&mut Executable>,
+    mut network: ResMut<RadioNetwork>,
+    tick: Res<SimulationTick>,
+) {
+    let (due, in_flight): (Vec<_>, Vec<_>) = std::mem::take(&mut network.in_flight)
+        .into_iter()
+        .partition(|p| p.deliver_at <= tick.0);
+    network.in_flight = in_flight;
+
+    for in_flight in due {
+        if let Ok(mut executable) = query.get_mut(in_flight.receiver) {
+            executable.device.radio.receive(in_flight.packet);
+        }
+    }
+}
+
+/// Runs the reception vector for every packet waiting in each unit's inbox. Messages sent
+/// from the handlers go out on the next tick.
+fn handle_radio_inbox(
+    mut query: Query<(Entity, &mut Executable, &mut Transform)>,
+    mut out_radio_messages: EventWriter<RadioMessage>,
 ) {
-    for msg in in_radio_messages.read() {
-        for (entity, mut executable, mut transform) in &mut query {
-            let entity_freq = executable.radio_frequency();
-
-            if msg.origin_entity_id.unwrap() != entity && entity_freq == msg.frequency {
-                let message_vec = executable.radio_message_vector();
-                executable.set_radio_packets(&msg.packets);
-                executable.pc = Some(message_vec);
-
-                if let Some(mut _rm) = executable.cont(&mut transform) {
-                    // rm.origin_entity_id = Some(entity);
-                    // out_radio_messages.send(rm);
-                }
+    for (entity, mut executable, mut transform) in &mut query {
+        while executable.pc.is_none() {
+            let Some(packet) = executable.device.radio.inbox.pop_front() else {
+                break;
+            };
+
+            let message_vec = executable.radio_message_vector();
+            executable.deliver_radio_packet(&packet);
+            if message_vec == 0 {
+                continue;
+            }
+            executable.pc = Some(message_vec);
+            executable.handled_hops = Some(packet.hops);
+
+            for mut rm in executable.cont(&mut transform) {
+                rm.origin_entity_id = Some(entity);
+                out_radio_messages.send(rm);
             }
         }
     }
@@ -35,6 +272,17 @@ pub struct RadioPlugin;
 impl Plugin for RadioPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<RadioMessage>()
-            .add_systems(Update, route_radio_messages);
+            .add_event::<RadioTransmission>()
+            .init_resource::<RadioNetworkConfig>()
+            .init_resource::<RadioNetwork>()
+            .add_systems(
+                PostUpdate,
+                (
+                    transmit_radio_messages,
+                    deliver_radio_messages,
+                    handle_radio_inbox,
+                )
+                    .chain(),
+            );
     }
 }
@@ -0,0 +1,278 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::components::InstanceId;
+use crate::radio::{transmit_radio_messages, RadioTransmission};
+use crate::timer::SimulationTick;
+
+/// Most transmissions kept in the log, older ones are forgotten
+pub const TRAFFIC_LOG_CAPACITY: usize = 1024;
+/// How long a transmission stays drawn on the map
+pub const VISUALIZE_TICKS: u64 = 30;
+
+pub struct LoggedTransmission {
+    pub transmission: RadioTransmission,
+    /// Handles of the receivers, looked up when the transmission was logged
+    pub receiver_handles: Vec<u16>,
+}
+
+impl LoggedTransmission {
+    fn involves(&self, handle: u16) -> bool {
+        self.transmission.sender_handle == handle || self.receiver_handles.contains(&handle)
+    }
+}
+
+#[derive(Serialize)]
+struct TrafficRecord<'a> {
+    tick: u64,
+    sender: Option<String>,
+    sender_handle: u16,
+    x: f32,
+    y: f32,
+    range: f32,
+    frequency: u8,
+    destination: Option<u16>,
+    payload: String,
+    hops: u8,
+    receivers: &'a [u16],
+}
+
+#[derive(Resource)]
+pub struct RadioTrafficLog {
+    pub entries: VecDeque<LoggedTransmission>,
+    pub paused: bool,
+}
+
+impl Default for RadioTrafficLog {
+    fn default() -> Self {
+        RadioTrafficLog {
+            entries: VecDeque::with_capacity(TRAFFIC_LOG_CAPACITY),
+            paused: false,
+        }
+    }
+}
+
+impl RadioTrafficLog {
+    pub fn push(&mut self, entry: LoggedTransmission) {
+        if self.entries.len() >= TRAFFIC_LOG_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Writes every logged transmission as one JSON object per line
+    pub fn export_jsonl(&self, path: &str) -> std::io::Result<usize> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        for entry in &self.entries {
+            let t = &entry.transmission;
+            let record = TrafficRecord {
+                tick: t.tick,
+                sender: t.sender.map(|e| e.to_string()),
+                sender_handle: t.sender_handle,
+                x: t.origin_position.x,
+                y: t.origin_position.y,
+                range: t.range,
+                frequency: t.frequency,
+                destination: t.destination,
+                payload: hex(&t.payload),
+                hops: t.hops,
+                receivers: &entry.receiver_handles,
+            };
+            serde_json::to_writer(&mut writer, &record)?;
+            writeln!(writer)?;
+        }
+
+        writer.flush()?;
+        Ok(self.entries.len())
+    }
+}
+
+#[derive(Resource)]
+struct RadioInspectorState {
+    frequency_filter: Option<u8>,
+    unit_filter: Option<u16>,
+    show_on_map: bool,
+    export_path: String,
+    export_status: String,
+}
+
+impl Default for RadioInspectorState {
+    fn default() -> Self {
+        RadioInspectorState {
+            frequency_filter: None,
+            unit_filter: None,
+            show_on_map: true,
+            export_path: "radio_traffic.jsonl".to_string(),
+            export_status: String::new(),
+        }
+    }
+}
+
+impl RadioInspectorState {
+    fn shows(&self, entry: &LoggedTransmission) -> bool {
+        self.frequency_filter
+            .is_none_or(|f| f == entry.transmission.frequency)
+            && self.unit_filter.is_none_or(|h| entry.involves(h))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn frequency_color(frequency: u8) -> Color {
+    Color::hsl(frequency as f32 / 256.0 * 360.0, 0.8, 0.6)
+}
+
+fn record_radio_traffic(
+    mut transmissions: EventReader<RadioTransmission>,
+    mut log: ResMut<RadioTrafficLog>,
+    instance_ids: Query<&InstanceId>,
+) {
+    for transmission in transmissions.read() {
+        if log.paused {
+            continue;
+        }
+
+        let receiver_handles = transmission
+            .receivers
+            .iter()
+            .filter_map(|e| instance_ids.get(*e).ok())
+            .map(|id| id.0)
+            .collect();
+
+        log.push(LoggedTransmission {
+            transmission: transmission.clone(),
+            receiver_handles,
+        });
+    }
+}
+
+fn draw_radio_inspector(
+    mut context: EguiContexts,
+    mut log: ResMut<RadioTrafficLog>,
+    mut state: ResMut<RadioInspectorState>,
+) {
+    egui::Window::new("Radio Traffic".to_string())
+        .default_open(false)
+        .show(context.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                let mut filter = state.frequency_filter.is_some();
+                ui.checkbox(&mut filter, "Frequency");
+                let mut frequency = state.frequency_filter.unwrap_or(0);
+                ui.add_enabled(filter, egui::DragValue::new(&mut frequency).hexadecimal(2, false, true));
+                state.frequency_filter = filter.then_some(frequency);
+            });
+            ui.horizontal(|ui| {
+                let mut filter = state.unit_filter.is_some();
+                ui.checkbox(&mut filter, "Unit");
+                let mut handle = state.unit_filter.unwrap_or(0);
+                ui.add_enabled(filter, egui::DragValue::new(&mut handle).hexadecimal(4, false, true));
+                state.unit_filter = filter.then_some(handle);
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut state.show_on_map, "Show on map");
+                ui.checkbox(&mut log.paused, "Pause");
+                if ui.button("Clear").clicked() {
+                    log.entries.clear();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut state.export_path);
+                if ui.button("Export JSONL").clicked() {
+                    state.export_status = match log.export_jsonl(&state.export_path) {
+                        Ok(n) => format!("Exported {} transmissions", n),
+                        Err(e) => format!("Export failed: {}", e),
+                    };
+                }
+            });
+            if !state.export_status.is_empty() {
+                ui.label(&state.export_status);
+            }
+
+            ui.separator();
+
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("radio_traffic").striped(true).show(ui, |ui| {
+                        ui.label("tick");
+                        ui.label("sender");
+                        ui.label("freq");
+                        ui.label("payload");
+                        ui.label("receivers");
+                        ui.end_row();
+
+                        for entry in log.entries.iter().filter(|e| state.shows(e)) {
+                            let t = &entry.transmission;
+                            ui.label(t.tick.to_string());
+                            match t.sender {
+                                Some(e) => ui.label(format!("{:04X} ({})", t.sender_handle, e)),
+                                None => ui.label("-"),
+                            };
+                            ui.label(format!("{:02X}", t.frequency));
+                            ui.monospace(hex(&t.payload));
+                            let receivers: Vec<String> = entry
+                                .receiver_handles
+                                .iter()
+                                .map(|h| format!("{:04X}", h))
+                                .collect();
+                            match t.destination {
+                                Some(d) => ui.label(format!("to {:04X}: {}", d, receivers.join(" "))),
+                                None => ui.label(receivers.join(" ")),
+                            };
+                            ui.end_row();
+                        }
+                    });
+                });
+        });
+}
+
+/// Draws the range of recent transmissions and a line to every unit that received them
+fn draw_radio_traffic(
+    mut gizmos: Gizmos,
+    log: Res<RadioTrafficLog>,
+    state: Res<RadioInspectorState>,
+    tick: Res<SimulationTick>,
+    transforms: Query<&Transform>,
+) {
+    if !state.show_on_map {
+        return;
+    }
+
+    let recent = log
+        .entries
+        .iter()
+        .rev()
+        .take_while(|e| e.transmission.tick + VISUALIZE_TICKS >= tick.0)
+        .filter(|e| state.shows(e));
+
+    for entry in recent {
+        let t = &entry.transmission;
+        let color = frequency_color(t.frequency);
+        gizmos.circle_2d(t.origin_position, t.range, color.with_alpha(0.3));
+
+        for receiver in &t.receivers {
+            if let Ok(transform) = transforms.get(*receiver) {
+                gizmos.line_2d(t.origin_position, transform.translation.xy(), color);
+            }
+        }
+    }
+}
+
+pub struct RadioInspectorPlugin;
+
+impl Plugin for RadioInspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RadioTrafficLog>()
+            .init_resource::<RadioInspectorState>()
+            .add_systems(PostUpdate, record_radio_traffic.after(transmit_radio_messages))
+            .add_systems(Update, (draw_radio_inspector, draw_radio_traffic));
+    }
+}
@@ -0,0 +1,27 @@
+use bevy::prelude::*;
+
+/// Environment variable used to override the world seed
+pub const WORLD_SEED_ENV: &str = "KIKAI_SEED";
+const DEFAULT_WORLD_SEED: u64 = 0x6B69_6B61_6900_0000;
+
+/// Seed every unit's Random device is derived from, along with the unit's `InstanceId`
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct WorldSeed(pub u64);
+
+impl WorldSeed {
+    pub fn from_env() -> Self {
+        let seed = std::env::var(WORLD_SEED_ENV)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_WORLD_SEED);
+        WorldSeed(seed)
+    }
+}
+
+pub struct RandomPlugin;
+
+impl Plugin for RandomPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WorldSeed::from_env());
+    }
+}
@@ -7,10 +7,22 @@ use bevy_egui::{egui, EguiContexts};
 use rand::Rng;
 use regex::Regex;
 
-use crate::executable::CodeReloadEvent;
-use crate::tools::assembler::assemble;
-use crate::unit_repo::{UnitDefinition, UnitRepository};
-use crate::unit_spawn::SpawnUnitRequest;
+use crate::assets::AssetLibrary;
+use crate::devices::OPTIONAL_DEVICES;
+use crate::economy::{SpawnDepotRequest, TeamResources};
+use crate::executable::{CodeReloadEvent, CodeReloaded, ReloadMode};
+use crate::radio::RadioNetworkConfig;
+use crate::tools::assembler::{assemble_with_diagnostics, AssembleError, Program};
+use crate::tools::diff::DiffLine;
+use crate::unit_export::{
+    export_unit_type, import_unit_type, read_bundle, write_bundle, ConflictPolicy,
+};
+use crate::unit_repo::{
+    RepoResult, UnitDefinition, UnitRepository, UnitStats, UnitVersion, VersionStatus,
+};
+use crate::unit_spawn::{SpawnUnitRequest, UnitSpawnFailed, UnitTypeDeleted};
+use crate::bundles::unit::Unit;
+use crate::workspace::{is_valid_name, SwitchWorkspace, WorkspaceSwitchFailed, Workspaces};
 
 enum SandboxUIMode {
     MainMenu,
@@ -24,12 +36,110 @@ struct SandboxState {
     units: Vec<UnitDefinition>,
     editor_open: bool,
     current_code: String,
+    /// The editor's code differs from the unit type's current version
     is_modified: bool,
+    /// Why the editor's code didn't assemble the last time it was tried. The assembler
+    /// stops at the first error, so there's at most one.
+    problem: Option<AssembleError>,
+    spawn_team: u8,
+    history_open: bool,
+    history: Vec<UnitVersion>,
+    /// Version picked in the history list and the one it's compared against
+    history_selected: Option<u64>,
+    history_base: Option<u64>,
+    history_diff: Vec<DiffLine>,
+    /// Last thing that went wrong, shown until dismissed
+    error: Option<String>,
+    /// Stats of the selected unit type being edited
+    stats_draft: UnitStats,
+    bundle_path: String,
+    bundle_full_history: bool,
+    bundle_conflict_policy: ConflictPolicy,
+    bundle_status: String,
+    /// Name typed in to create a new workspace
+    new_workspace: String,
+    /// Workspace to switch to once it's confirmed that unsaved code and units can go
+    pending_workspace: Option<String>,
+    /// Name for renaming or duplicating the selected unit type
+    manage_name: String,
+    /// Delete was clicked once and waits for confirmation
+    confirm_delete: bool,
+    /// Whether live units keep their memory when the editor reloads their code
+    reload_mode: ReloadMode,
+    /// What the last state preserving reload did
+    reload_report: Option<CodeReloaded>,
 }
 
 impl SandboxState {
+    /// Keeps the error of a failed repository call around to show it
+    fn report<T>(&mut self, result: RepoResult<T>) -> Option<T> {
+        result.inspect_err(|e| self.error = Some(e.to_string())).ok()
+    }
+
     pub fn refresh_units(&mut self, repo: &UnitRepository) {
-        self.units = repo.get_units();
+        let units = repo.get_units();
+        self.units = self.report(units).unwrap_or_default();
+        self.selected_unit = self
+            .selected_unit
+            .as_ref()
+            .and_then(|selected| self.units.iter().find(|u| u.unit_id == selected.unit_id))
+            .cloned();
+    }
+
+    /// Switches the editor, stats and history over to another unit type
+    fn select_unit(&mut self, unit: Option<UnitDefinition>, repo: &UnitRepository) {
+        self.current_code = unit.as_ref().and_then(|u| u.code.clone()).unwrap_or_default();
+        self.is_modified = false;
+        self.problem = None;
+        self.stats_draft = unit.as_ref().map(|u| u.stats.clone()).unwrap_or_default();
+        self.manage_name = unit.as_ref().map(|u| u.name.clone()).unwrap_or_default();
+        self.confirm_delete = false;
+        self.selected_unit = unit;
+        self.refresh_history(repo);
+    }
+
+    /// Assembles the editor's code, keeping what's wrong with it around to show it
+    fn assemble_editor_code(&mut self) -> Option<Program> {
+        match assemble_with_diagnostics(self.current_code.clone()) {
+            Ok(program) => {
+                self.problem = None;
+                Some(program)
+            }
+            Err(problem) => {
+                self.problem = Some(problem);
+                None
+            }
+        }
+    }
+
+    fn editor_status(&self) -> String {
+        match (&self.selected_unit, self.is_modified) {
+            (None, _) => "No unit type selected".to_string(),
+            (Some(_), true) => "Modified".to_string(),
+            (Some(unit), false) => match unit.current_version_id {
+                Some(version_id) => format!("Saved as #{}", version_id),
+                None => "Not saved yet".to_string(),
+            },
+        }
+    }
+
+    pub fn refresh_history(&mut self, repo: &UnitRepository) {
+        let history = match &self.selected_unit {
+            Some(unit) => repo.list_versions(unit.unit_id),
+            None => Ok(Vec::new()),
+        };
+        self.history = self.report(history).unwrap_or_default();
+        self.history_selected = None;
+        self.history_base = self.selected_unit.as_ref().and_then(|u| u.current_version_id);
+        self.history_diff.clear();
+    }
+
+    fn refresh_diff(&mut self, repo: &UnitRepository) {
+        let diff = match (self.history_base, self.history_selected) {
+            (Some(base), Some(selected)) => repo.diff_versions(base, selected),
+            _ => Ok(None),
+        };
+        self.history_diff = self.report(diff).flatten().unwrap_or_default();
     }
 }
 
@@ -42,6 +152,25 @@ impl Default for SandboxState {
             editor_open: false,
             current_code: "".to_string(),
             is_modified: false,
+            problem: None,
+            spawn_team: 0,
+            history_open: false,
+            history: Vec::new(),
+            history_selected: None,
+            history_base: None,
+            history_diff: Vec::new(),
+            error: None,
+            stats_draft: UnitStats::default(),
+            bundle_path: String::new(),
+            bundle_full_history: true,
+            bundle_conflict_policy: ConflictPolicy::Rename,
+            bundle_status: String::new(),
+            new_workspace: String::new(),
+            manage_name: String::new(),
+            confirm_delete: false,
+            pending_workspace: None,
+            reload_mode: ReloadMode::default(),
+            reload_report: None,
         }
     }
 }
@@ -49,10 +178,42 @@ impl Default for SandboxState {
 fn draw_sandbox_ui(
     mut context: EguiContexts,
     mut spawn_events: EventWriter<SpawnUnitRequest>,
+    mut depot_events: EventWriter<SpawnDepotRequest>,
     mut sandbox_state: ResMut<SandboxState>,
     repo: Res<UnitRepository>,
+    resources: Res<TeamResources>,
+    mut radio_config: ResMut<RadioNetworkConfig>,
+    mut spawn_failures: EventReader<UnitSpawnFailed>,
+    asset_lib: Res<AssetLibrary>,
+    workspaces: Res<Workspaces>,
+    mut workspace_events: EventWriter<SwitchWorkspace>,
+    mut workspace_failures: EventReader<WorkspaceSwitchFailed>,
+    mut deleted_events: EventWriter<UnitTypeDeleted>,
+    units: Query<(), With<Unit>>,
 ) {
+    for failure in spawn_failures.read() {
+        sandbox_state.error = Some(format!(
+            "Couldn't spawn unit type {}: {}",
+            failure.unit_id, failure.reason
+        ));
+    }
+    for failure in workspace_failures.read() {
+        sandbox_state.error = Some(format!(
+            "Couldn't open workspace {}: {}",
+            failure.name, failure.reason
+        ));
+    }
+
     egui::Window::new("Sandbox".to_string()).show(context.ctx_mut(), |ui| {
+        if let Some(error) = sandbox_state.error.clone() {
+            ui.horizontal(|ui| {
+                ui.colored_label(Color32::from_rgb(0xCC, 0x33, 0x33), error);
+                if ui.button("Dismiss").clicked() {
+                    sandbox_state.error = None;
+                }
+            });
+        }
+
         match &mut sandbox_state.mode {
             SandboxUIMode::MainMenu => {
                 if ui.button("New Unit Type").clicked() {
@@ -76,26 +237,92 @@ fn draw_sandbox_ui(
                     });
 
                 if sandbox_state.selected_unit != selected_unit {
-                    sandbox_state.current_code = selected_unit
-                        .as_ref()
-                        .unwrap()
-                        .code
-                        .clone()
-                        .unwrap_or_else(|| String::new());
-                    sandbox_state.selected_unit = selected_unit;
+                    sandbox_state.select_unit(selected_unit, &repo);
+                }
+
+                if sandbox_state.selected_unit.is_some() {
+                    egui::CollapsingHeader::new("Unit Stats").show(ui, |ui| {
+                        draw_stats_editor(ui, &mut sandbox_state, &repo, &asset_lib);
+                    });
+                    egui::CollapsingHeader::new("Manage Unit Type").show(ui, |ui| {
+                        draw_manage_controls(ui, &mut sandbox_state, &repo, &mut deleted_events);
+                    });
                 }
 
-                if ui.button("Create Unit").clicked() {
+                ui.add(egui::DragValue::new(&mut sandbox_state.spawn_team).prefix("Team: "));
+
+                let spawnable = sandbox_state
+                    .selected_unit
+                    .as_ref()
+                    .is_some_and(|u| u.code.is_some());
+                let create_unit = ui
+                    .add_enabled(spawnable, egui::Button::new("Create Unit"))
+                    .on_disabled_hover_text("Select a unit type with saved code");
+                if create_unit.clicked() {
                     let mut rng = rand::rng();
 
                     let rx: i8 = rng.random();
                     let ry: i8 = rng.random();
                     spawn_events.send(SpawnUnitRequest {
-                        unit_id: 1,
+                        unit_id: sandbox_state.selected_unit.as_ref().unwrap().unit_id,
+                        team: sandbox_state.spawn_team,
+                        position: Vec2::new(rx as f32, ry as f32),
+                        producer: None,
+                    });
+                }
+
+                if ui.button("Create Depot").clicked() {
+                    let mut rng = rand::rng();
+
+                    let rx: i8 = rng.random();
+                    let ry: i8 = rng.random();
+                    depot_events.send(SpawnDepotRequest {
+                        team: sandbox_state.spawn_team,
                         position: Vec2::new(rx as f32, ry as f32),
                     });
                 }
 
+                egui::CollapsingHeader::new("Workspace").show(ui, |ui| {
+                    draw_workspace_controls(
+                        ui,
+                        &mut sandbox_state,
+                        &workspaces,
+                        !units.is_empty(),
+                        &mut workspace_events,
+                    );
+                });
+
+                egui::CollapsingHeader::new("Import / Export").show(ui, |ui| {
+                    draw_bundle_controls(ui, &mut sandbox_state, &repo);
+                });
+
+                egui::CollapsingHeader::new("Resources").show(ui, |ui| {
+                    let mut teams = resources.teams();
+                    if !teams.iter().any(|(team, _)| *team == sandbox_state.spawn_team) {
+                        teams.push((
+                            sandbox_state.spawn_team,
+                            resources.get(sandbox_state.spawn_team),
+                        ));
+                        teams.sort();
+                    }
+
+                    for (team, stock) in teams {
+                        ui.label(format!("Team {}: {}", team, stock));
+                    }
+                });
+
+                egui::CollapsingHeader::new("Radio Network").show(ui, |ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut radio_config.latency_ticks)
+                            .range(0..=600)
+                            .prefix("Latency (ticks): "),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut radio_config.loss_probability, 0.0..=1.0)
+                            .text("Packet loss"),
+                    );
+                });
+
                 if sandbox_state.editor_open {
                     if ui.button("Close Code Editor").clicked() {
                         sandbox_state.editor_open = false;
@@ -105,6 +332,17 @@ fn draw_sandbox_ui(
                         sandbox_state.editor_open = true;
                     }
                 }
+
+                if sandbox_state.history_open {
+                    if ui.button("Close History").clicked() {
+                        sandbox_state.history_open = false;
+                    }
+                } else {
+                    if ui.button("Open History").clicked() {
+                        sandbox_state.refresh_history(&repo);
+                        sandbox_state.history_open = true;
+                    }
+                }
             }
             SandboxUIMode::CreateUnit { ref mut unit_name } => {
                 let name_to_create = unit_name.clone();
@@ -112,7 +350,8 @@ fn draw_sandbox_ui(
 
                 ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
                     if ui.button("Create").clicked() {
-                        repo.new_unit_type(name_to_create);
+                        let created = repo.new_unit_type(name_to_create);
+                        sandbox_state.report(created);
                         sandbox_state.refresh_units(&repo);
                         sandbox_state.mode = SandboxUIMode::MainMenu;
                     }
@@ -126,6 +365,277 @@ fn draw_sandbox_ui(
     });
 }
 
+fn draw_stats_editor(
+    ui: &mut egui::Ui,
+    sandbox_state: &mut SandboxState,
+    repo: &UnitRepository,
+    asset_lib: &AssetLibrary,
+) {
+    let mut sprites: Vec<&String> = asset_lib.assets["roguelike"].mappings.keys().collect();
+    sprites.sort();
+
+    let stats = &mut sandbox_state.stats_draft;
+    egui::ComboBox::from_label("Sprite")
+        .selected_text(stats.sprite.clone())
+        .show_ui(ui, |ui| {
+            for sprite in sprites {
+                ui.selectable_value(&mut stats.sprite, sprite.clone(), sprite);
+            }
+        });
+    ui.add(
+        egui::DragValue::new(&mut stats.scale)
+            .range(0.5..=8.0)
+            .speed(0.1)
+            .prefix("Scale: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut stats.max_speed)
+            .range(0.0..=50.0)
+            .speed(0.1)
+            .prefix("Max speed: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut stats.max_hp)
+            .range(1..=u16::MAX)
+            .prefix("HP: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut stats.armor)
+            .range(0..=u16::MAX)
+            .prefix("Armor: "),
+    );
+    ui.add(
+        egui::DragValue::new(&mut stats.cycle_budget)
+            .range(1..=100_000)
+            .prefix("Cycles per tick: "),
+    );
+
+    ui.label("Devices");
+    egui::Grid::new("installed_devices").num_columns(3).show(ui, |ui| {
+        for (i, (name, page)) in OPTIONAL_DEVICES.iter().enumerate() {
+            let mut installed = stats.devices.is_installed(*page);
+            if ui.checkbox(&mut installed, *name).changed() {
+                stats.devices.set(*page, installed);
+            }
+            if i % 3 == 2 {
+                ui.end_row();
+            }
+        }
+    });
+
+    let unit = sandbox_state.selected_unit.as_ref().unwrap();
+    let modified = unit.stats != sandbox_state.stats_draft;
+    if ui
+        .add_enabled(modified, egui::Button::new("Save Stats"))
+        .on_hover_text("Applies to units spawned from now on")
+        .clicked()
+    {
+        let saved = repo.update_unit_stats(unit.unit_id, &sandbox_state.stats_draft);
+        if sandbox_state.report(saved).is_some() {
+            sandbox_state.refresh_units(repo);
+        }
+    }
+}
+
+/// Rename, duplicate and delete for the selected unit type
+fn draw_manage_controls(
+    ui: &mut egui::Ui,
+    sandbox_state: &mut SandboxState,
+    repo: &UnitRepository,
+    deleted_events: &mut EventWriter<UnitTypeDeleted>,
+) {
+    let Some(unit) = sandbox_state.selected_unit.clone() else {
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Name:");
+        ui.text_edit_singleline(&mut sandbox_state.manage_name);
+    });
+
+    let name = sandbox_state.manage_name.trim().to_string();
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(!name.is_empty() && name != unit.name, egui::Button::new("Rename"))
+            .clicked()
+        {
+            let renamed = repo.rename_unit_type(unit.unit_id, name.clone());
+            if sandbox_state.report(renamed).is_some() {
+                sandbox_state.refresh_units(repo);
+            }
+        }
+
+        if ui
+            .add_enabled(!name.is_empty() && name != unit.name, egui::Button::new("Duplicate"))
+            .on_hover_text("New unit type with these stats and the current code")
+            .clicked()
+        {
+            let duplicated = repo.duplicate_unit_type(unit.unit_id, name.clone());
+            if let Some(unit_id) = sandbox_state.report(duplicated) {
+                sandbox_state.refresh_units(repo);
+                let copy = sandbox_state.units.iter().find(|u| u.unit_id == unit_id).cloned();
+                sandbox_state.select_unit(copy, repo);
+            }
+        }
+    });
+
+    if !sandbox_state.confirm_delete {
+        if ui.button("Delete").clicked() {
+            sandbox_state.confirm_delete = true;
+        }
+        return;
+    }
+
+    ui.colored_label(
+        Color32::from_rgb(0xCC, 0x33, 0x33),
+        format!(
+            "Delete {} with all its versions and storage? Its units on the map are removed too.",
+            unit.name
+        ),
+    );
+    ui.horizontal(|ui| {
+        if ui.button("Delete").clicked() {
+            let deleted = repo.delete_unit_type(unit.unit_id);
+            if sandbox_state.report(deleted).is_some() {
+                deleted_events.send(UnitTypeDeleted {
+                    unit_id: unit.unit_id,
+                });
+                sandbox_state.select_unit(None, repo);
+                sandbox_state.refresh_units(repo);
+            }
+            sandbox_state.confirm_delete = false;
+        }
+        if ui.button("Cancel").clicked() {
+            sandbox_state.confirm_delete = false;
+        }
+    });
+}
+
+fn draw_bundle_controls(ui: &mut egui::Ui, sandbox_state: &mut SandboxState, repo: &UnitRepository) {
+    ui.horizontal(|ui| {
+        ui.label("File:");
+        ui.text_edit_singleline(&mut sandbox_state.bundle_path);
+    });
+
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut sandbox_state.bundle_full_history, "Full history");
+
+        let selected = sandbox_state.selected_unit.clone();
+        if ui
+            .add_enabled(selected.is_some(), egui::Button::new("Export"))
+            .clicked()
+        {
+            let unit = selected.unwrap();
+            if sandbox_state.bundle_path.is_empty() {
+                sandbox_state.bundle_path = format!("{}.json", unit.name);
+            }
+
+            let bundle = export_unit_type(repo, unit.unit_id, sandbox_state.bundle_full_history);
+            if let Some(bundle) = sandbox_state.report(bundle) {
+                sandbox_state.bundle_status = match write_bundle(&bundle, &sandbox_state.bundle_path) {
+                    Ok(()) => format!("Exported {} to {}", bundle.name, sandbox_state.bundle_path),
+                    Err(e) => format!("Export failed: {}", e),
+                };
+            }
+        }
+    });
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_label("On name conflict")
+            .selected_text(sandbox_state.bundle_conflict_policy.name())
+            .show_ui(ui, |ui| {
+                for policy in ConflictPolicy::ALL {
+                    ui.selectable_value(
+                        &mut sandbox_state.bundle_conflict_policy,
+                        policy,
+                        policy.name(),
+                    );
+                }
+            });
+
+        if ui.button("Import").clicked() {
+            match read_bundle(&sandbox_state.bundle_path) {
+                Ok(bundle) => {
+                    let imported =
+                        import_unit_type(repo, &bundle, sandbox_state.bundle_conflict_policy);
+                    if let Some(unit_id) = sandbox_state.report(imported) {
+                        sandbox_state.bundle_status =
+                            format!("Imported {} as unit type {}", bundle.name, unit_id);
+                        sandbox_state.refresh_units(repo);
+                    }
+                }
+                Err(e) => sandbox_state.bundle_status = format!("Import failed: {}", e),
+            }
+        }
+    });
+
+    if !sandbox_state.bundle_status.is_empty() {
+        ui.label(&sandbox_state.bundle_status);
+    }
+}
+
+fn draw_workspace_controls(
+    ui: &mut egui::Ui,
+    sandbox_state: &mut SandboxState,
+    workspaces: &Workspaces,
+    has_units: bool,
+    workspace_events: &mut EventWriter<SwitchWorkspace>,
+) {
+    if let Some(name) = sandbox_state.pending_workspace.clone() {
+        ui.colored_label(
+            Color32::from_rgb(0xCC, 0x33, 0x33),
+            format!(
+                "Switch to {}? Unsaved code in the editor is lost and units on the map are removed.",
+                name
+            ),
+        );
+        ui.horizontal(|ui| {
+            if ui.button("Switch").clicked() {
+                workspace_events.send(SwitchWorkspace(name));
+                sandbox_state.pending_workspace = None;
+            }
+            if ui.button("Cancel").clicked() {
+                sandbox_state.pending_workspace = None;
+            }
+        });
+        return;
+    }
+
+    let mut requested = None;
+    let mut selected = workspaces.current.clone();
+    egui::ComboBox::from_label("Current")
+        .selected_text(&selected)
+        .show_ui(ui, |ui| {
+            for name in workspaces.list() {
+                let label = name.clone();
+                ui.selectable_value(&mut selected, name, label);
+            }
+        });
+    if selected != workspaces.current {
+        requested = Some(selected);
+    }
+
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut sandbox_state.new_workspace);
+        let valid = is_valid_name(&sandbox_state.new_workspace);
+        if ui
+            .add_enabled(valid, egui::Button::new("Create"))
+            .on_disabled_hover_text("Letters, digits, - and _ only")
+            .clicked()
+        {
+            requested = Some(std::mem::take(&mut sandbox_state.new_workspace));
+        }
+    });
+
+    if let Some(name) = requested {
+        if sandbox_state.is_modified || has_units {
+            sandbox_state.pending_workspace = Some(name);
+        } else {
+            workspace_events.send(SwitchWorkspace(name));
+        }
+    }
+}
+
 // fn editor_layouter(ui: &egui::Ui, string: &str, wrap_width: u32) -> Arc<egui::Galley> {
 // }
 
@@ -153,19 +663,38 @@ fn color_for_tok(tok: &str) -> Color32 {
 fn draw_editor_window(
     mut context: EguiContexts,
     mut code_reload_events: EventWriter<CodeReloadEvent>,
+    mut reloaded_events: EventReader<CodeReloaded>,
     mut sandbox_state: ResMut<SandboxState>,
     repo: Res<UnitRepository>,
 ) {
+    for reloaded in reloaded_events.read() {
+        if reloaded.units > 0 && sandbox_state.reload_mode == ReloadMode::PreserveState {
+            sandbox_state.reload_report = Some(reloaded.clone());
+        }
+    }
+
+    let problem_range = sandbox_state.problem.as_ref().map(|p| (p.start, p.end));
     let mut layouter = |ui: &egui::Ui, string: &str, _wrap_width: f32| {
         let tokens = tokenize(string);
         let mut job = LayoutJob::default();
+        let mut offset = 0;
 
         for tok in tokens {
+            let end = offset + tok.len();
+            let has_problem =
+                problem_range.is_some_and(|(start, stop)| offset < stop && start < end);
+            offset = end;
+
             job.append(
                 tok,
                 0.0,
                 TextFormat {
                     color: color_for_tok(tok),
+                    underline: if has_problem {
+                        egui::Stroke::new(2.0, Color32::from_rgb(0xCC, 0x33, 0x33))
+                    } else {
+                        egui::Stroke::NONE
+                    },
                     ..Default::default()
                 },
             );
@@ -176,28 +705,64 @@ fn draw_editor_window(
 
     if sandbox_state.editor_open {
         egui::Window::new("Editor".to_string()).show(context.ctx_mut(), |ui| {
+            let unit_id = sandbox_state.selected_unit.as_ref().map(|u| u.unit_id);
+
             ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
-                if ui.button("Save").clicked() {
-                    repo.update_code_for_unit(
-                        sandbox_state.selected_unit.as_ref().unwrap().unit_id,
-                        sandbox_state.current_code.clone(),
-                    );
+                if ui.add_enabled(unit_id.is_some(), egui::Button::new("Assemble")).clicked() {
+                    if let Some(program) = sandbox_state.assemble_editor_code() {
+                        code_reload_events.send(CodeReloadEvent {
+                            program,
+                            unit_id: unit_id.unwrap(),
+                            version_id: None,
+                            mode: sandbox_state.reload_mode,
+                        });
+                    }
                 }
-                if ui.button("Assemble").clicked() {
-                    let program = assemble(sandbox_state.current_code.clone());
-                    code_reload_events.send(CodeReloadEvent {
-                        program: program.clone().unwrap(),
-                        unit_id: sandbox_state.selected_unit.as_ref().unwrap().unit_id,
-                    });
+                if ui
+                    .add_enabled(unit_id.is_some(), egui::Button::new("Assemble & Save"))
+                    .clicked()
+                {
+                    if let Some(program) = sandbox_state.assemble_editor_code() {
+                        let saved =
+                            repo.update_code_for_unit(unit_id.unwrap(), sandbox_state.current_code.clone());
+                        if let Some(version_id) = sandbox_state.report(saved) {
+                            code_reload_events.send(CodeReloadEvent {
+                                program,
+                                unit_id: unit_id.unwrap(),
+                                version_id: Some(version_id),
+                                mode: sandbox_state.reload_mode,
+                            });
+                            sandbox_state.is_modified = false;
+                        }
+                        sandbox_state.refresh_units(&repo);
+                        sandbox_state.refresh_history(&repo);
+                    }
                 }
-                if ui.button("Assemble & Save").clicked() {}
+                let mut preserve = sandbox_state.reload_mode == ReloadMode::PreserveState;
+                ui.checkbox(&mut preserve, "Keep unit state")
+                    .on_hover_text("Live units keep the memory of labels that didn't change");
+                sandbox_state.reload_mode = if preserve {
+                    ReloadMode::PreserveState
+                } else {
+                    ReloadMode::Reset
+                };
             });
-            egui::TextEdit::multiline(&mut sandbox_state.current_code)
+            ui.label(sandbox_state.editor_status());
+            if let Some(problem) = &sandbox_state.problem {
+                ui.colored_label(Color32::from_rgb(0xCC, 0x33, 0x33), problem.to_string());
+            }
+            draw_reload_report(ui, &mut sandbox_state);
+            let output = egui::TextEdit::multiline(&mut sandbox_state.current_code)
                 .code_editor()
                 .layouter(&mut layouter)
                 .desired_width(f32::INFINITY)
                 .desired_rows(10)
                 .show(ui);
+            if output.response.changed() {
+                sandbox_state.is_modified = true;
+                // The positions are stale once the code changes
+                sandbox_state.problem = None;
+            }
 
             // let hover_pos = ui.input(|i| {
             //     i.pointer.hover_pos()
@@ -217,7 +782,144 @@ fn draw_editor_window(
     }
 }
 
-fn initialize_sandbox_state(mut sandbox_state: ResMut<SandboxState>, repo: Res<UnitRepository>) {
+fn draw_reload_report(ui: &mut egui::Ui, sandbox_state: &mut SandboxState) {
+    let Some(reloaded) = &sandbox_state.reload_report else {
+        return;
+    };
+
+    let mut dismiss = false;
+    egui::CollapsingHeader::new(format!("Reloaded {} units", reloaded.units))
+        .id_salt("reload_report")
+        .show(ui, |ui| {
+            let report = &reloaded.report;
+            if !report.migrated.is_empty() {
+                ui.colored_label(
+                    Color32::from_rgb(0x1F, 0xC7, 0x42),
+                    format!("Kept: {}", report.migrated.join(" ")),
+                );
+            }
+            if !report.reset.is_empty() {
+                ui.colored_label(
+                    Color32::from_rgb(0xCC, 0x99, 0x33),
+                    format!("Reset: {}", report.reset.join(" ")),
+                );
+            }
+            dismiss = ui.button("Dismiss").clicked();
+        });
+
+    if dismiss {
+        sandbox_state.reload_report = None;
+    }
+}
+
+fn version_label(version: &UnitVersion, current: Option<u64>) -> String {
+    let mut label = format!("#{} {}", version.version_id, version.created_at);
+    if current == Some(version.version_id) {
+        label.push_str(" (current)");
+    }
+    if version.status == VersionStatus::Broken {
+        label.push_str(" (broken)");
+    }
+    label
+}
+
+fn draw_history_window(
+    mut context: EguiContexts,
+    mut sandbox_state: ResMut<SandboxState>,
+    repo: Res<UnitRepository>,
+) {
+    if !sandbox_state.history_open {
+        return;
+    }
+
+    egui::Window::new("History".to_string()).show(context.ctx_mut(), |ui| {
+        let Some(unit) = sandbox_state.selected_unit.clone() else {
+            ui.label("Select a unit type to see its history");
+            return;
+        };
+        let current = unit.current_version_id;
+
+        let mut selected = sandbox_state.history_selected;
+        egui::ScrollArea::vertical()
+            .id_salt("history_versions")
+            .max_height(150.0)
+            .show(ui, |ui| {
+                for version in &sandbox_state.history {
+                    ui.selectable_value(
+                        &mut selected,
+                        Some(version.version_id),
+                        version_label(version, current),
+                    );
+                }
+            });
+
+        let mut base = sandbox_state.history_base;
+        egui::ComboBox::from_label("Compare with")
+            .selected_text(base.map_or_else(|| "None".to_string(), |b| format!("#{}", b)))
+            .show_ui(ui, |ui| {
+                for version in &sandbox_state.history {
+                    ui.selectable_value(
+                        &mut base,
+                        Some(version.version_id),
+                        version_label(version, current),
+                    );
+                }
+            });
+
+        if selected != sandbox_state.history_selected || base != sandbox_state.history_base {
+            sandbox_state.history_selected = selected;
+            sandbox_state.history_base = base;
+            sandbox_state.refresh_diff(&repo);
+        }
+
+        let Some(selected) = selected else {
+            return;
+        };
+
+        let build_error = sandbox_state
+            .history
+            .iter()
+            .find(|v| v.version_id == selected)
+            .and_then(|v| v.build_error.clone());
+
+        if let Some(error) = &build_error {
+            ui.colored_label(Color32::from_rgb(0xCC, 0x33, 0x33), error);
+        } else if selected != current.unwrap_or(0) && ui.button("Restore").clicked() {
+            let restored = repo.set_current_version(unit.unit_id, selected);
+            if sandbox_state.report(restored) == Some(true) {
+                sandbox_state.refresh_units(&repo);
+                if let Some(code) = sandbox_state.selected_unit.as_ref().and_then(|u| u.code.clone()) {
+                    sandbox_state.current_code = code;
+                    sandbox_state.is_modified = false;
+                    sandbox_state.problem = None;
+                }
+                sandbox_state.refresh_history(&repo);
+                return;
+            }
+        }
+
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .id_salt("history_diff")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for line in &sandbox_state.history_diff {
+                    let (prefix, text, color) = match line {
+                        DiffLine::Same(text) => (" ", text, Color32::from_rgb(0x8E, 0xA3, 0xA6)),
+                        DiffLine::Added(text) => ("+", text, Color32::from_rgb(0x1F, 0xC7, 0x42)),
+                        DiffLine::Removed(text) => ("-", text, Color32::from_rgb(0xCC, 0x33, 0x33)),
+                    };
+                    ui.colored_label(color, egui::RichText::new(format!("{} {}", prefix, text)).monospace());
+                }
+            });
+    });
+}
+
+/// Reloads the unit types whenever the repository is replaced, at startup and when
+/// switching workspaces
+fn reload_sandbox_state(mut sandbox_state: ResMut<SandboxState>, repo: Res<UnitRepository>) {
+    sandbox_state.select_unit(None, &repo);
     sandbox_state.refresh_units(&repo);
 }
 
@@ -226,7 +928,13 @@ pub struct SandboxPlugin;
 impl Plugin for SandboxPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource::<SandboxState>(SandboxState::default())
-            .add_systems(Startup, initialize_sandbox_state)
-            .add_systems(Update, (draw_sandbox_ui, draw_editor_window));
+            .add_systems(
+                Update,
+                (
+                    reload_sandbox_state.run_if(resource_changed::<UnitRepository>),
+                    (draw_sandbox_ui, draw_editor_window, draw_history_window),
+                )
+                    .chain(),
+            );
     }
 }
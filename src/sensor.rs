@@ -0,0 +1,74 @@
+use bevy::prelude::*;
+
+use crate::components::{Depot, Executable, ResourceNode, Team};
+use crate::devices::sensor::{ScanResult, KIND_DEPOT, KIND_RESOURCE, KIND_UNIT};
+use crate::executable::update_executables;
+use crate::spatial::SpatialIndex;
+
+fn resolve_scans(
+    mut query: Query<(Entity, &mut Executable, &Transform, &Team)>,
+    nodes: Query<&ResourceNode>,
+    depots: Query<&Team, With<Depot>>,
+    index: Res<SpatialIndex>,
+) {
+    let pending: Vec<(Entity, Vec2, u8, u16)> = query
+        .iter_mut()
+        .filter_map(|(entity, mut executable, transform, team)| {
+            executable
+                .device
+                .sensor
+                .pending_scan
+                .take()
+                .map(|radius| (entity, transform.translation.xy(), team.0, radius))
+        })
+        .collect();
+
+    for (entity, pos, team, radius) in pending {
+        let results: Vec<ScanResult> = index
+            .query_radius(pos, radius as f32)
+            .into_iter()
+            .filter(|(other, _)| *other != entity)
+            .filter_map(|(other, other_pos)| {
+                let (kind, unit_type, friend) =
+                    if let Ok((_, executable, _, other_team)) = query.get(other) {
+                        (KIND_UNIT, executable.unit_type_id as u16, other_team.0 == team)
+                    } else if let Ok(node) = nodes.get(other) {
+                        (KIND_RESOURCE, node.amount, false)
+                    } else if let Ok(depot_team) = depots.get(other) {
+                        (KIND_DEPOT, 0, depot_team.0 == team)
+                    } else {
+                        return None;
+                    };
+
+                let delta = other_pos - pos;
+                Some(ScanResult {
+                    dx: delta.x as i16,
+                    dy: delta.y as i16,
+                    kind,
+                    unit_type,
+                    friend,
+                })
+            })
+            .collect();
+
+        let Ok((_, mut executable, _, _)) = query.get_mut(entity) else {
+            continue;
+        };
+
+        let Executable { cpu, device, .. } = &mut *executable;
+        device.sensor.complete_scan(cpu, results);
+
+        let scan_vec = executable.scan_vector();
+        if scan_vec != 0 {
+            executable.vector_queue.push(scan_vec);
+        }
+    }
+}
+
+pub struct SensorPlugin;
+
+impl Plugin for SensorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, resolve_scans.after(update_executables));
+    }
+}
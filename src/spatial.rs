@@ -0,0 +1,98 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::bundles::unit::Unit;
+use crate::components::{Depot, ResourceNode};
+
+const CELL_SIZE: f32 = 128.0;
+
+/// Uniform grid over units, resource nodes and depots, rebuilt every frame, used to
+/// answer "what is near this point" without walking every entity in the world.
+#[derive(Resource, Default)]
+pub struct SpatialIndex {
+    cells: HashMap<(i32, i32), Vec<(Entity, Vec2)>>,
+}
+
+fn cell_of(pos: Vec2) -> (i32, i32) {
+    (
+        (pos.x / CELL_SIZE).floor() as i32,
+        (pos.y / CELL_SIZE).floor() as i32,
+    )
+}
+
+impl SpatialIndex {
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    pub fn insert(&mut self, entity: Entity, pos: Vec2) {
+        self.cells.entry(cell_of(pos)).or_default().push((entity, pos));
+    }
+
+    /// Returns every entity within `radius` of `center`, closest first
+    pub fn query_radius(&self, center: Vec2, radius: f32) -> Vec<(Entity, Vec2)> {
+        let (min_x, min_y) = cell_of(center - Vec2::splat(radius));
+        let (max_x, max_y) = cell_of(center + Vec2::splat(radius));
+        let mut found = Vec::new();
+
+        for cx in min_x..=max_x {
+            for cy in min_y..=max_y {
+                if let Some(entries) = self.cells.get(&(cx, cy)) {
+                    found.extend(
+                        entries
+                            .iter()
+                            .filter(|(_, pos)| pos.distance(center) <= radius)
+                            .copied(),
+                    );
+                }
+            }
+        }
+
+        found.sort_by(|(_, a), (_, b)| a.distance(center).total_cmp(&b.distance(center)));
+        found
+    }
+}
+
+fn rebuild_spatial_index(
+    mut index: ResMut<SpatialIndex>,
+    query: Query<(Entity, &Transform), Or<(With<Unit>, With<ResourceNode>, With<Depot>)>>,
+) {
+    index.clear();
+    for (entity, transform) in &query {
+        index.insert(entity, transform.translation.xy());
+    }
+}
+
+pub struct SpatialPlugin;
+
+impl Plugin for SpatialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpatialIndex>()
+            .add_systems(PreUpdate, rebuild_spatial_index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_radius_filters_and_sorts() {
+        let mut index = SpatialIndex::default();
+        let far = Entity::from_raw(1);
+        let near = Entity::from_raw(2);
+        let other_cell = Entity::from_raw(3);
+
+        index.insert(far, Vec2::new(500., 500.));
+        index.insert(near, Vec2::new(10., 0.));
+        index.insert(other_cell, Vec2::new(-130., 0.));
+
+        let found: Vec<Entity> = index
+            .query_radius(Vec2::ZERO, 200.)
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect();
+
+        assert_eq!(found, vec![near, other_cell]);
+    }
+}
@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+
+use crate::components::{Executable, InstanceKey};
+use crate::devices::storage::StorageOp;
+use crate::executable::update_executables;
+use crate::unit_repo::UnitRepository;
+
+fn serve_storage_requests(
+    mut query: Query<(&mut Executable, &InstanceKey)>,
+    repo: Res<UnitRepository>,
+) {
+    for (mut executable, instance_key) in &mut query {
+        let requests = std::mem::take(&mut executable.device.storage.pending);
+        if requests.is_empty() {
+            continue;
+        }
+
+        let unit_type_id = executable.unit_type_id;
+
+        for request in requests {
+            let scope = if request.per_instance { instance_key.0 } else { 0 };
+
+            let success = match request.op {
+                StorageOp::Read { addr, length } => {
+                    let blob = repo
+                        .read_blob(unit_type_id, scope, &request.name)
+                        .inspect_err(|e| eprintln!("Storage read failed: {}", e))
+                        .ok()
+                        .flatten()
+                        .unwrap_or_default();
+                    let n = blob.len().min(length as usize);
+                    for (i, byte) in blob[..n].iter().enumerate() {
+                        executable
+                            .cpu
+                            .ram_write_byte(addr.wrapping_add(i as u16), *byte);
+                    }
+                    n as u16
+                }
+                StorageOp::Write { data, append } => {
+                    match repo.write_blob(unit_type_id, scope, &request.name, &data, append) {
+                        Ok(true) => data.len() as u16,
+                        Ok(false) => 0,
+                        Err(e) => {
+                            eprintln!("Storage write failed: {}", e);
+                            0
+                        }
+                    }
+                }
+                StorageOp::Delete => match repo.delete_blob(unit_type_id, scope, &request.name) {
+                    Ok(deleted) => deleted as u16,
+                    Err(e) => {
+                        eprintln!("Storage delete failed: {}", e);
+                        0
+                    }
+                },
+            };
+
+            let Executable { cpu, device, .. } = &mut *executable;
+            device.storage.complete(cpu, success);
+
+            let storage_vec = executable.storage_vector();
+            if storage_vec != 0 {
+                executable.vector_queue.push(storage_vec);
+            }
+        }
+    }
+}
+
+pub struct StoragePlugin;
+
+impl Plugin for StoragePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, serve_storage_requests.after(update_executables));
+    }
+}
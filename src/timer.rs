@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+
+use crate::components::Executable;
+use crate::executable::update_executables;
+
+/// Number of simulation ticks since startup, advanced once per frame
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct SimulationTick(pub u64);
+
+fn advance_tick(mut tick: ResMut<SimulationTick>) {
+    tick.0 += 1;
+}
+
+fn fire_timers(tick: Res<SimulationTick>, mut query: Query<&mut Executable>) {
+    for mut executable in &mut query {
+        let due = executable.device.timer.advance(tick.0);
+        executable.vector_queue.extend(due);
+    }
+}
+
+pub struct TimerPlugin;
+
+impl Plugin for TimerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationTick>()
+            .add_systems(First, advance_tick)
+            .add_systems(Update, fire_timers.before(update_executables));
+    }
+}
@@ -584,56 +584,64 @@ impl Lexer {
         }
     }
 
-    pub fn next_span(&mut self) -> Option<Span> {
-        if self.cursor >= self.string.len() {
-            None
-        } else {
-            while self
-                .string
-                .chars()
-                .nth(self.cursor)
-                .unwrap()
-                .is_whitespace()
-            {
-                self.cursor += 1;
-            }
-
-            let src_string: String = self.string[self.cursor..]
-                .chars()
-                .take_while(|c| !c.is_whitespace())
-                .collect();
+    /// Error for the source between byte offsets `start` and `end`
+    fn error_at(&self, start: usize, end: usize, message: String) -> AssembleError {
+        let before = self.string.get(..start).unwrap_or(&self.string);
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
 
-            let start = self.cursor;
-            let end = self.cursor + src_string.len();
+        AssembleError {
+            message,
+            start,
+            end,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
 
-            self.cursor = end + 1;
+    /// Lexes the next whitespace separated chunk, `cursor` and the span offsets are byte
+    /// offsets into the source
+    pub fn next_span(&mut self) -> Option<Result<Span, AssembleError>> {
+        let (skipped, _) = self.string[self.cursor..]
+            .char_indices()
+            .find(|(_, c)| !c.is_whitespace())?;
+        let start = self.cursor + skipped;
+        let end = self.string[start..]
+            .char_indices()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(self.string.len(), |(i, _)| start + i);
 
-            println!("{} ({}-{})", src_string, start, end);
+        self.cursor = end;
+        let src_string = self.string[start..end].to_string();
 
-            let atom = self.lex(&src_string);
+        let atom = match self.lex(&src_string) {
+            Ok(atom) => atom,
+            Err(e) => return Some(Err(self.error_at(start, end, e))),
+        };
 
-            Some(Span {
-                atom,
-                src_string,
-                start: self.cursor,
-                end: self.cursor + 4,
-            })
-        }
+        Some(Ok(Span {
+            atom,
+            src_string,
+            start,
+            end,
+        }))
     }
 
-    pub fn lex(&mut self, chunk: &String) -> Atom {
-        if chunk.starts_with('#') {
+    pub fn lex(&mut self, chunk: &String) -> Result<Atom, String> {
+        let hex_u8 = |s: &str| u8::from_str_radix(s, 16).map_err(|_| format!("Invalid hex number {}", chunk));
+        let hex_u16 = |s: &str| u16::from_str_radix(s, 16).map_err(|_| format!("Invalid hex number {}", chunk));
+
+        let atom = if chunk.starts_with('#') {
             if chunk[1..].len() == 2 {
-                Atom::ByteLiteral(u8::from_str_radix(&chunk[1..], 16).unwrap())
+                Atom::ByteLiteral(hex_u8(&chunk[1..])?)
             } else if chunk[1..].len() == 4 {
-                Atom::ShortLiteral(u16::from_str_radix(&chunk[1..], 16).unwrap())
+                Atom::ShortLiteral(hex_u16(&chunk[1..])?)
             } else {
-                panic!("Wrong Literal");
+                return Err(format!("Wrong literal {}", chunk));
             }
         } else if chunk.starts_with('|') {
-            Atom::AbsolutePadding(u16::from_str_radix(&chunk[1..], 16).unwrap())
+            Atom::AbsolutePadding(hex_u16(&chunk[1..])?)
         } else if chunk.starts_with('$') {
-            Atom::RelativePadding(u16::from_str_radix(&chunk[1..], 16).unwrap())
+            Atom::RelativePadding(hex_u16(&chunk[1..])?)
         } else if chunk.starts_with('?') {
             Atom::ImmediateJCI(chunk[2..].to_string())
         } else if chunk.starts_with('"') {
@@ -666,10 +674,12 @@ impl Lexer {
         } else if let Some(instr) = parse_instruction(chunk) {
             Atom::Instr(instr)
         } else if chunk.chars().next().map_or(false, |c| c.is_ascii_digit()) {
-            Atom::ByteRaw(u8::from_str_radix(chunk, 16).unwrap())
+            Atom::ByteRaw(hex_u8(chunk)?)
         } else {
             Atom::ProcCall(chunk.to_string())
-        }
+        };
+
+        Ok(atom)
     }
 }
 
@@ -704,9 +714,48 @@ pub fn rom_size(atom: &Atom) -> u16 {
 pub struct Program {
     pub rom: Vec<u8>,
     pub symbol_table: BTreeMap<String, u16>,
+    /// Bytes declared under each label, up to the next label of the same or a higher level
+    /// or the next absolute padding. Labels in the device layout have size 0.
+    pub label_sizes: BTreeMap<String, u16>,
+}
+
+/// Records the size of the label that was open, now that it ended at `end`
+fn close_label(open: &mut Option<(String, u16)>, end: u16, sizes: &mut BTreeMap<String, u16>) {
+    if let Some((name, start)) = open.take() {
+        sizes.insert(name, end.wrapping_sub(start));
+    }
+}
+
+/// Why some code didn't assemble, and where
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssembleError {
+    pub message: String,
+    /// Byte range of the offending token in the source
+    pub start: usize,
+    pub end: usize,
+    /// Line and column (in characters) of the token, starting at 1
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Device layouts follow the Uxn convention of capitalised labels in the zero page
+/// (`|10 @Console &write $1`), lowercase ones there are variables (`|00 @count $1`)
+fn is_device_label(label: &str, addr: u16) -> bool {
+    addr < 0x100 && label.starts_with(|c: char| c.is_ascii_uppercase())
 }
 
 pub fn assemble(src: String) -> Result<Program, String> {
+    assemble_with_diagnostics(src).map_err(|e| e.to_string())
+}
+
+/// Same as `assemble`, with the location of the error for editors to point at
+pub fn assemble_with_diagnostics(src: String) -> Result<Program, AssembleError> {
     let mut lexer = Lexer::new(src);
 
     let mut curr_addr: u16 = 0;
@@ -715,25 +764,60 @@ pub fn assemble(src: String) -> Result<Program, String> {
     let mut program = Program {
         rom: vec![],
         symbol_table: BTreeMap::new(),
+        label_sizes: BTreeMap::new(),
     };
 
     let mut spans = vec![];
     let mut current_scope = "".to_string();
+    // Whether the current label names device ports rather than memory, see `is_device_label`
+    let mut in_device_layout = false;
+    let mut open_label: Option<(String, u16)> = None;
+    let mut open_sublabel: Option<(String, u16)> = None;
 
     while let Some(span) = lexer.next_span() {
+        // Whatever is in a comment doesn't have to make sense
+        let span = match span {
+            Err(_) if in_comment => continue,
+            span => span?,
+        };
         match &span.atom {
+            Atom::LParen => {
+                in_comment = true;
+                continue;
+            }
+            Atom::RParen => {
+                in_comment = false;
+                continue;
+            }
+            _ if in_comment => continue,
             Atom::AbsoluteLabel(label) => {
+                close_label(&mut open_sublabel, curr_addr, &mut program.label_sizes);
+                close_label(&mut open_label, curr_addr, &mut program.label_sizes);
                 program.symbol_table.insert(label.to_string(), curr_addr);
                 current_scope = label.to_string();
+                in_device_layout = is_device_label(label, curr_addr);
+                if in_device_layout {
+                    program.label_sizes.insert(label.to_string(), 0);
+                } else {
+                    open_label = Some((label.to_string(), curr_addr));
+                }
             }
             Atom::RelativeLabel(label) => {
+                close_label(&mut open_sublabel, curr_addr, &mut program.label_sizes);
                 let full_label = format!("{}/{}", current_scope, label);
-                program.symbol_table.insert(full_label, curr_addr);
+                program.symbol_table.insert(full_label.clone(), curr_addr);
+                if in_device_layout {
+                    program.label_sizes.insert(full_label, 0);
+                } else {
+                    open_sublabel = Some((full_label, curr_addr));
+                }
             }
             Atom::RelativePadding(pad) => {
                 curr_addr += pad;
             }
             Atom::AbsolutePadding(addr) => {
+                close_label(&mut open_sublabel, curr_addr, &mut program.label_sizes);
+                close_label(&mut open_label, curr_addr, &mut program.label_sizes);
                 curr_addr = *addr;
             }
             atom => curr_addr += rom_size(atom),
@@ -741,22 +825,17 @@ pub fn assemble(src: String) -> Result<Program, String> {
 
         spans.push(span);
     }
-
-    println!("{:?}", program.symbol_table);
+    close_label(&mut open_sublabel, curr_addr, &mut program.label_sizes);
+    close_label(&mut open_label, curr_addr, &mut program.label_sizes);
 
     let mut current_scope = "".to_string();
+    let fail = |span: &Span, message: String| lexer.error_at(span.start, span.end, message);
 
     curr_addr = 0;
 
     for span in spans {
         curr_addr += rom_size(&span.atom);
         match &span.atom {
-            Atom::LParen => {
-                in_comment = true;
-            }
-            Atom::RParen => {
-                in_comment = false;
-            }
             Atom::LBracket | Atom::RBracket => {}
             Atom::Instr(instr) => {
                 program.rom.push((*instr).into());
@@ -785,7 +864,10 @@ pub fn assemble(src: String) -> Result<Program, String> {
             }
             Atom::LiteralAbsoluteAddressing(label) => {
                 program.rom.push(Instr::LIT2.into());
-                let bytes = program.symbol_table[label].to_be_bytes();
+                let Some(addr) = program.symbol_table.get(label) else {
+                    return Err(fail(&span, format!("Couldn't find label {}", label)));
+                };
+                let bytes = addr.to_be_bytes();
                 program.rom.push(bytes[0]);
                 program.rom.push(bytes[1]);
             }
@@ -795,7 +877,7 @@ pub fn assemble(src: String) -> Result<Program, String> {
                     let bytes = lit.to_be_bytes();
                     program.rom.push(bytes[1]);
                 } else {
-                    panic!("Couldn't find label {}", label);
+                    return Err(fail(&span, format!("Couldn't find label {}", label)));
                 }
             }
             Atom::ByteRaw(byte) => {
@@ -810,7 +892,7 @@ pub fn assemble(src: String) -> Result<Program, String> {
                     program.rom.push(bytes[0]);
                     program.rom.push(bytes[1]);
                 } else {
-                    panic!("ImmediateJCI: Couldn't find label {}", full_label);
+                    return Err(fail(&span, format!("ImmediateJCI: Couldn't find label {}", full_label)));
                 }
             }
             Atom::ProcCall(label) => {
@@ -821,19 +903,19 @@ pub fn assemble(src: String) -> Result<Program, String> {
                     program.rom.push(bytes[0]);
                     program.rom.push(bytes[1]);
                 } else {
-                    panic!("ProcCall: Couldn't find label {}", label);
+                    return Err(fail(&span, format!("ProcCall: Couldn't find label {}", label)));
                 }
             }
             Atom::StringLiteral(text) => {
                 if !text.is_ascii() {
-                    panic!("Only ascii supported!");
+                    return Err(fail(&span, "Only ascii supported!".to_string()));
                 }
 
                 for ch in text.chars() {
                     program.rom.push(ch as u8);
                 }
             }
-            _ => unimplemented!("{:?}", span.atom),
+            atom => return Err(fail(&span, format!("Unsupported {:?} at {}", atom, span.src_string))),
         }
     }
 
@@ -995,8 +1077,28 @@ mod tests {
         let expected_program = Program {
             rom: expected.clone(),
             symbol_table: BTreeMap::new(),
+            label_sizes: BTreeMap::new(),
         };
 
         assert_eq!(program.rom, expected)
     }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert!(assemble("|100 #123 BRK".to_string()).is_err());
+        assert!(assemble("|100 ;missing BRK".to_string()).is_err());
+        assert!(assemble("|100 missing BRK".to_string()).is_err());
+        assert!(assemble("|100 #01 POP BRK\n\n".to_string()).is_ok());
+
+        let error = assemble_with_diagnostics("|100\n#01 ;missing BRK".to_string()).unwrap_err();
+        assert_eq!((error.line, error.column), (2, 5));
+        assert_eq!((error.start, error.end), (9, 17));
+        assert_eq!(error.to_string(), "2:5: Couldn't find label missing");
+
+        // Offsets count bytes, columns count characters
+        let error = assemble_with_diagnostics("|100\n( café ) ;missing".to_string()).unwrap_err();
+        assert_eq!((error.start, error.end), (15, 23));
+        assert_eq!(&"|100\n( café ) ;missing"[error.start..error.end], ";missing");
+        assert_eq!((error.line, error.column), (2, 10));
+    }
 }
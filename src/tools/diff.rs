@@ -0,0 +1,68 @@
+/// A line in a diff between two texts
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// Line by line diff from `old` to `new`, based on their longest common subsequence
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            diff.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|l| DiffLine::Removed(l.to_string())));
+    diff.extend(new[j..].iter().map(|l| DiffLine::Added(l.to_string())));
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_diff() {
+        let old = "|0100\n#01 #02 ADD\nBRK\n";
+        let new = "|0100\n#01 #03 ADD\nPOP\nBRK\n";
+
+        assert_eq!(
+            line_diff(old, new),
+            vec![
+                DiffLine::Same("|0100".to_string()),
+                DiffLine::Removed("#01 #02 ADD".to_string()),
+                DiffLine::Added("#01 #03 ADD".to_string()),
+                DiffLine::Added("POP".to_string()),
+                DiffLine::Same("BRK".to_string()),
+            ]
+        );
+        assert_eq!(line_diff("", "a"), vec![DiffLine::Added("a".to_string())]);
+    }
+}
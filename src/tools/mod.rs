@@ -1 +1,3 @@
 pub mod assembler;
+pub mod diff;
+pub mod symbols;
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+
+use super::assembler::Program;
+
+/// Address programs are loaded at, everything below is the zero page
+const ROM_START: u16 = 0x100;
+
+/// Memory declared under a label
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub addr: u16,
+    pub size: u16,
+}
+
+/// Carrying a labeled region over from the old program's memory into the new one's
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionMove {
+    pub name: String,
+    pub from: u16,
+    pub to: u16,
+    pub size: u16,
+}
+
+/// What a state preserving reload is going to do with the new program's labels
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MigrationPlan {
+    pub moves: Vec<RegionMove>,
+    /// Labels of the new program that start out fresh
+    pub reset: Vec<String>,
+}
+
+/// Sizes every label by what the program declared under it, or by the distance to the next
+/// label for programs assembled before sizes were recorded. Zero page labels end at the
+/// zero page, the rest at the end of the ROM, so labels past it get no region. Device
+/// layout labels name ports rather than memory and get no region either.
+pub fn symbol_regions(program: &Program) -> BTreeMap<String, Region> {
+    let rom_end = (ROM_START as usize + program.rom.len()).min(0x10000) as u32;
+
+    let mut by_addr: Vec<(&String, u16)> =
+        program.symbol_table.iter().map(|(name, addr)| (name, *addr)).collect();
+    by_addr.sort_by_key(|(_, addr)| *addr);
+
+    let mut regions = BTreeMap::new();
+    for (i, (name, addr)) in by_addr.iter().enumerate() {
+        let zone_end = if *addr < ROM_START { ROM_START as u32 } else { rom_end };
+        let end = match program.label_sizes.get(*name) {
+            Some(size) => *addr as u32 + *size as u32,
+            None => by_addr[i + 1..]
+                .iter()
+                .map(|(_, next)| *next as u32)
+                .find(|next| *next > *addr as u32)
+                .unwrap_or(zone_end),
+        }
+        .min(zone_end);
+
+        if end > *addr as u32 {
+            regions.insert(
+                name.to_string(),
+                Region {
+                    addr: *addr,
+                    size: (end - *addr as u32) as u16,
+                },
+            );
+        }
+    }
+
+    regions
+}
+
+/// Bytes a region starts out with when the program is loaded, zeros outside the ROM
+fn initial_bytes(program: &Program, region: Region) -> Vec<u8> {
+    (0..region.size)
+        .map(|i| {
+            let addr = region.addr.wrapping_add(i);
+            addr.checked_sub(ROM_START)
+                .and_then(|offset| program.rom.get(offset as usize))
+                .copied()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Works out which regions keep their contents going from `old` to `new`. A region is
+/// kept when a label with the same name and size exists in both programs; outside the
+/// zero page it also has to start out with the same bytes, so changed code and data
+/// whose declaration changed start fresh.
+pub fn plan_state_migration(old: &Program, new: &Program) -> MigrationPlan {
+    let old_regions = symbol_regions(old);
+    let mut plan = MigrationPlan::default();
+
+    for (name, region) in symbol_regions(new) {
+        let kept = old_regions.get(&name).filter(|old_region| {
+            old_region.size == region.size
+                && (region.addr < ROM_START) == (old_region.addr < ROM_START)
+                && (region.addr < ROM_START
+                    || initial_bytes(old, **old_region) == initial_bytes(new, region))
+        });
+
+        match kept {
+            Some(old_region) => plan.moves.push(RegionMove {
+                name,
+                from: old_region.addr,
+                to: region.addr,
+                size: region.size,
+            }),
+            None => plan.reset.push(name),
+        }
+    }
+
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::assembler::assemble;
+
+    #[test]
+    fn test_plan_state_migration() {
+        let old = assemble("|0000 @count $2 @flag $1 @old $1 |100 @main #01 POP BRK".to_string()).unwrap();
+        let new = assemble(
+            "|0000 @flag $1 @count $2 @other $1 @wide $1 |100 @main #02 POP BRK".to_string(),
+        )
+        .unwrap();
+
+        let plan = plan_state_migration(&old, &new);
+        assert_eq!(
+            plan.moves,
+            vec![
+                RegionMove {
+                    name: "count".to_string(),
+                    from: 0x00,
+                    to: 0x01,
+                    size: 2,
+                },
+                RegionMove {
+                    name: "flag".to_string(),
+                    from: 0x02,
+                    to: 0x00,
+                    size: 1,
+                },
+            ]
+        );
+        assert_eq!(plan.reset, vec!["main", "other", "wide"]);
+
+        let unchanged = plan_state_migration(&old, &old);
+        assert!(unchanged.reset.is_empty());
+        assert_eq!(unchanged.moves.len(), 4);
+    }
+
+    #[test]
+    fn test_regions_follow_declared_sizes() {
+        let old = assemble("|10 @Console &write $1 |0000 @a $1 @b $1 |100 BRK".to_string()).unwrap();
+        let new =
+            assemble("|10 @Console &write $1 |0000 @a $1 @b $1 @c $1 |100 BRK".to_string()).unwrap();
+
+        let regions = symbol_regions(&new);
+        assert!(!regions.contains_key("Console"));
+        assert!(!regions.contains_key("Console/write"));
+        assert_eq!(regions["c"], Region { addr: 0x02, size: 1 });
+
+        // Appending a variable leaves the ones before it alone
+        let plan = plan_state_migration(&old, &new);
+        let kept: Vec<&str> = plan.moves.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(kept, vec!["a", "b"]);
+        assert_eq!(plan.reset, vec!["c"]);
+    }
+
+    #[test]
+    fn test_zero_page_variables_without_memory_padding() {
+        let program = assemble("|00 @var $1 |10 @Console &write $1 |100 BRK".to_string()).unwrap();
+
+        let regions = symbol_regions(&program);
+        assert_eq!(regions["var"], Region { addr: 0x00, size: 1 });
+        assert!(!regions.contains_key("Console"));
+        assert!(!regions.contains_key("Console/write"));
+    }
+}
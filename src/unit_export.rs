@@ -0,0 +1,248 @@
+//! Portable unit type files, for sharing unit types between databases.
+//!
+//! A bundle is a JSON file holding a unit type's metadata and the source of its current
+//! version or of its whole history, along with the assembled ROM and symbol table of
+//! every version. On import every version is assembled again from its source, the ROM is
+//! only there for tools that want to run the program without an assembler.
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::devices::InstalledDevices;
+use crate::unit_repo::{RepoError, RepoResult, UnitRepository, UnitStats};
+
+/// Bumped whenever the bundle format changes in a way older builds can't read
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BundledStats {
+    pub sprite: String,
+    pub scale: f32,
+    pub max_speed: f32,
+    pub max_hp: u16,
+    /// Missing from bundles exported before unit types had armor
+    #[serde(default)]
+    pub armor: u16,
+    pub cycle_budget: u32,
+    pub devices: u16,
+}
+
+impl From<&UnitStats> for BundledStats {
+    fn from(stats: &UnitStats) -> Self {
+        BundledStats {
+            sprite: stats.sprite.clone(),
+            scale: stats.scale,
+            max_speed: stats.max_speed,
+            max_hp: stats.max_hp,
+            armor: stats.armor,
+            cycle_budget: stats.cycle_budget,
+            devices: stats.devices.0,
+        }
+    }
+}
+
+impl From<&BundledStats> for UnitStats {
+    fn from(stats: &BundledStats) -> Self {
+        UnitStats {
+            sprite: stats.sprite.clone(),
+            scale: stats.scale,
+            max_speed: stats.max_speed,
+            max_hp: stats.max_hp,
+            armor: stats.armor,
+            cycle_budget: stats.cycle_budget,
+            devices: InstalledDevices(stats.devices),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BundledVersion {
+    pub code: String,
+    pub created_at: String,
+    /// Base64 encoded ROM, missing for versions that don't assemble
+    pub rom: Option<String>,
+    pub symbols: Option<BTreeMap<String, u16>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UnitTypeBundle {
+    pub format_version: u32,
+    pub name: String,
+    pub stats: BundledStats,
+    /// Oldest first
+    pub versions: Vec<BundledVersion>,
+    /// Index in `versions` of the current version
+    pub current: Option<usize>,
+}
+
+/// What to do when importing a unit type whose name is already taken
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConflictPolicy {
+    /// Refuse to import
+    Fail,
+    /// Import under a new name, e.g. "scout (2)"
+    Rename,
+    /// Replace the existing type's metadata and add the bundle's versions to its history
+    Merge,
+}
+
+impl ConflictPolicy {
+    pub const ALL: [ConflictPolicy; 3] = [
+        ConflictPolicy::Fail,
+        ConflictPolicy::Rename,
+        ConflictPolicy::Merge,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Fail => "fail",
+            ConflictPolicy::Rename => "rename",
+            ConflictPolicy::Merge => "merge",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+/// Bundles a unit type, with only its current version or with its full history. Broken
+/// versions are bundled without a ROM.
+pub fn export_unit_type(
+    repo: &UnitRepository,
+    unit_id: u64,
+    full_history: bool,
+) -> RepoResult<UnitTypeBundle> {
+    let unit = repo.get_unit(unit_id)?;
+
+    let mut history = repo.list_versions(unit_id)?;
+    history.reverse();
+    if !full_history {
+        history.retain(|v| Some(v.version_id) == unit.current_version_id);
+    }
+
+    let current = history
+        .iter()
+        .position(|v| Some(v.version_id) == unit.current_version_id);
+
+    let versions = history
+        .into_iter()
+        .map(|v| {
+            let program = repo.get_version_program(v.version_id).ok();
+            BundledVersion {
+                rom: program.as_ref().map(|p| BASE64.encode(&p.rom)),
+                symbols: program.map(|p| p.symbol_table),
+                code: v.code,
+                created_at: v.created_at,
+            }
+        })
+        .collect();
+
+    Ok(UnitTypeBundle {
+        format_version: BUNDLE_FORMAT_VERSION,
+        name: unit.name,
+        stats: (&unit.stats).into(),
+        versions,
+        current,
+    })
+}
+
+/// Adds a bundled unit type to the repository, returning the id of the unit type that
+/// received it. Either all of it is imported or none of it is.
+pub fn import_unit_type(
+    repo: &UnitRepository,
+    bundle: &UnitTypeBundle,
+    policy: ConflictPolicy,
+) -> RepoResult<u64> {
+    let (unit_id, name) = match (repo.find_unit_by_name(&bundle.name)?, policy) {
+        (None, _) => (None, bundle.name.clone()),
+        (Some(_), ConflictPolicy::Fail) => return Err(RepoError::NameTaken(bundle.name.clone())),
+        (Some(_), ConflictPolicy::Rename) => {
+            let name = (2..)
+                .map(|i| format!("{} ({})", bundle.name, i))
+                .find(|name| !matches!(repo.find_unit_by_name(name), Ok(Some(_))))
+                .unwrap();
+            (None, name)
+        }
+        (Some(unit_id), ConflictPolicy::Merge) => (Some(unit_id), bundle.name.clone()),
+    };
+
+    let versions: Vec<(String, String)> = bundle
+        .versions
+        .iter()
+        .map(|v| (v.code.clone(), v.created_at.clone()))
+        .collect();
+    repo.import_unit_type(
+        unit_id,
+        &name,
+        &(&bundle.stats).into(),
+        &versions,
+        bundle.current,
+    )
+}
+
+pub fn write_bundle(bundle: &UnitTypeBundle, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path)?;
+    serde_json::to_writer_pretty(file, bundle)?;
+    Ok(())
+}
+
+pub fn read_bundle(path: impl AsRef<Path>) -> anyhow::Result<UnitTypeBundle> {
+    let file = std::fs::File::open(path)?;
+    let bundle: UnitTypeBundle = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+    if bundle.format_version > BUNDLE_FORMAT_VERSION {
+        anyhow::bail!(
+            "bundle format {} is newer than the supported {}",
+            bundle.format_version,
+            BUNDLE_FORMAT_VERSION
+        );
+    }
+
+    Ok(bundle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unit_repo::tests::test_repository;
+
+    #[test]
+    fn test_export_import_round_trip() {
+        let source = test_repository();
+        let unit_id = source.new_unit_type("scout".to_string()).unwrap();
+        source
+            .update_code_for_unit(unit_id, "|100 BRK".to_string())
+            .unwrap();
+        let _ = source.update_code_for_unit(unit_id, "|100 #123".to_string());
+        let current = source
+            .update_code_for_unit(unit_id, "|100 #01 POP BRK".to_string())
+            .unwrap();
+        source.set_current_version(unit_id, current).unwrap();
+
+        let bundle = export_unit_type(&source, unit_id, true).unwrap();
+        assert_eq!(bundle.versions.len(), 3);
+        assert_eq!(bundle.current, Some(2));
+        assert_eq!(bundle.versions[1].rom, None);
+        assert_eq!(export_unit_type(&source, unit_id, false).unwrap().versions.len(), 1);
+
+        let target = test_repository();
+        let imported = import_unit_type(&target, &bundle, ConflictPolicy::Fail).unwrap();
+        assert_eq!(
+            target.get_current_code_for_unit(imported).unwrap(),
+            "|100 #01 POP BRK"
+        );
+        assert_eq!(target.list_versions(imported).unwrap().len(), 3);
+
+        assert!(matches!(
+            import_unit_type(&target, &bundle, ConflictPolicy::Fail),
+            Err(RepoError::NameTaken(_))
+        ));
+        let renamed = import_unit_type(&target, &bundle, ConflictPolicy::Rename).unwrap();
+        assert_eq!(target.get_unit(renamed).unwrap().name, "scout (2)");
+        let merged = import_unit_type(&target, &bundle, ConflictPolicy::Merge).unwrap();
+        assert_eq!(merged, imported);
+        assert_eq!(target.list_versions(imported).unwrap().len(), 6);
+    }
+}
@@ -1,19 +1,183 @@
 use bevy::prelude::*;
-use rusqlite::{Connection, OpenFlags};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
 use rusqlite_migration::{Migrations, M};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::components::executable::DEFAULT_CYCLE_BUDGET;
+use crate::components::health::DEFAULT_HIT_POINTS;
+use crate::components::speed::DEFAULT_MAX_SPEED;
+use crate::devices::InstalledDevices;
+use crate::tools::assembler::{assemble, Program};
+use crate::tools::diff::{line_diff, DiffLine};
+use crate::workspace::{
+    switch_workspace, DbLocation, SwitchWorkspace, WorkspaceSwitchFailed, Workspaces,
+};
 
 pub struct UnitRepoPlugin;
 
+pub const DEFAULT_DB_PATH: &str = "./units.db";
+
+/// Largest blob a unit can store under a single name
+pub const MAX_BLOB_SIZE: usize = 4096;
+/// Most blobs a single storage scope can hold
+pub const MAX_BLOBS_PER_SCOPE: usize = 32;
+
+#[derive(Debug)]
+pub enum RepoError {
+    /// Every connection in the pool is in use
+    NoConnection,
+    Sqlite(rusqlite::Error),
+    UnitNotFound(u64),
+    /// The unit type exists but no code has been saved for it yet
+    NoCode(u64),
+    /// The version's code doesn't assemble
+    BrokenVersion { version_id: u64, error: String },
+    /// Another unit type already has this name
+    NameTaken(String),
+    Migration(rusqlite_migration::Error),
+}
+
+impl std::fmt::Display for RepoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepoError::NoConnection => write!(f, "no database connection available"),
+            RepoError::Sqlite(e) => write!(f, "database error: {}", e),
+            RepoError::UnitNotFound(unit_id) => write!(f, "unit type {} doesn't exist", unit_id),
+            RepoError::NoCode(unit_id) => write!(f, "unit type {} has no code yet", unit_id),
+            RepoError::BrokenVersion { version_id, error } => {
+                write!(f, "version {} doesn't assemble: {}", version_id, error)
+            }
+            RepoError::NameTaken(name) => write!(f, "a unit type named {} already exists", name),
+            RepoError::Migration(e) => write!(f, "migration failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RepoError {}
+
+impl From<rusqlite::Error> for RepoError {
+    fn from(e: rusqlite::Error) -> Self {
+        RepoError::Sqlite(e)
+    }
+}
+
+pub type RepoResult<T> = Result<T, RepoError>;
+
 #[derive(Resource)]
 pub struct UnitRepository {
     pool: rusqlite_pool::ConnectionPool,
 }
 
+pub const DEFAULT_SPRITE: &str = "medusa";
+pub const DEFAULT_UNIT_SCALE: f32 = 2.0;
+
+/// How units of a type look and what they're made of
+#[derive(Clone, PartialEq, Debug)]
+pub struct UnitStats {
+    /// Name of the sprite in the roguelike spritesheet
+    pub sprite: String,
+    pub scale: f32,
+    pub max_speed: f32,
+    pub max_hp: u16,
+    /// Flat amount taken off every hit units of this type take
+    pub armor: u16,
+    /// Cycles units of this type get to run every tick
+    pub cycle_budget: u32,
+    pub devices: InstalledDevices,
+}
+
+impl Default for UnitStats {
+    fn default() -> Self {
+        UnitStats {
+            sprite: DEFAULT_SPRITE.to_string(),
+            scale: DEFAULT_UNIT_SCALE,
+            max_speed: DEFAULT_MAX_SPEED,
+            max_hp: DEFAULT_HIT_POINTS,
+            armor: 0,
+            cycle_budget: DEFAULT_CYCLE_BUDGET,
+            devices: InstalledDevices::ALL,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct UnitDefinition {
     pub unit_id: u64,
     pub name: String,
     pub code: Option<String>,
+    pub current_version_id: Option<u64>,
+    pub stats: UnitStats,
+}
+
+const UNIT_COLUMNS: &str = "u.unit_id, u.name, uv.code, u.current_version_id,
+    u.sprite, u.scale, u.max_speed, u.max_hp, u.cycle_budget, u.devices, u.armor";
+
+/// Whether a version's code assembled when it was saved
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VersionStatus {
+    /// Saved before ROMs were stored, gets assembled the first time it's needed
+    Unbuilt,
+    Ok,
+    Broken,
+}
+
+impl VersionStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            VersionStatus::Unbuilt => "unbuilt",
+            VersionStatus::Ok => "ok",
+            VersionStatus::Broken => "broken",
+        }
+    }
+
+    fn parse(status: &str) -> Self {
+        match status {
+            "ok" => VersionStatus::Ok,
+            "broken" => VersionStatus::Broken,
+            _ => VersionStatus::Unbuilt,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct UnitVersion {
+    pub version_id: u64,
+    pub unit_id: u64,
+    pub code: String,
+    pub created_at: String,
+    pub status: VersionStatus,
+    /// Assembler error of broken versions
+    pub build_error: Option<String>,
+}
+
+/// Result of assembling a version's code, as stored alongside it
+struct BuildArtifact {
+    status: VersionStatus,
+    rom: Option<Vec<u8>>,
+    symbols: Option<String>,
+    label_sizes: Option<String>,
+    error: Option<String>,
+}
+
+impl BuildArtifact {
+    fn build(code: &str) -> Self {
+        match assemble(code.to_string()) {
+            Ok(program) => BuildArtifact {
+                status: VersionStatus::Ok,
+                rom: Some(program.rom),
+                symbols: serde_json::to_string(&program.symbol_table).ok(),
+                label_sizes: serde_json::to_string(&program.label_sizes).ok(),
+                error: None,
+            },
+            Err(error) => BuildArtifact {
+                status: VersionStatus::Broken,
+                rom: None,
+                symbols: None,
+                label_sizes: None,
+                error: Some(error),
+            },
+        }
+    }
 }
 
 impl UnitRepository {
@@ -26,71 +190,626 @@ impl UnitRepository {
         }
     }
 
-    pub fn get_connection(&self) -> Option<rusqlite_pool::ConnectionHandle> {
-        self.pool.pop()
-    }
+    /// Repository that lives only as long as it does, nothing is written to disk
+    pub fn in_memory() -> Self {
+        static NEXT_DB: AtomicUsize = AtomicUsize::new(0);
+        // Every connection to plain :memory: gets its own database, naming a shared cache
+        // one lets the pool's connections see the same data
+        let uri = format!(
+            "file:kikai-memory-{}?mode=memory&cache=shared",
+            NEXT_DB.fetch_add(1, Ordering::Relaxed)
+        );
 
-    pub fn get_units(&self) -> Vec<UnitDefinition> {
-        let conn = self.pool.pop().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT u.unit_id, u.name, uv.code FROM units AS u LEFT JOIN unit_versions AS uv ON (u.current_version_id = uv.version_id)"
-        ).unwrap();
-
-        let rows = stmt
-            .query_map([], |row| {
-                Ok(UnitDefinition {
-                    unit_id: row.get(0)?,
-                    name: row.get(1)?,
-                    code: row.get(2)?,
-                })
+        UnitRepository {
+            pool: rusqlite_pool::ConnectionPool::new(10, || {
+                Connection::open_with_flags(&uri, OpenFlags::default() | OpenFlags::SQLITE_OPEN_URI)
             })
-            .unwrap();
+            .unwrap(),
+        }
+    }
+
+    pub fn open(location: &DbLocation) -> Self {
+        match location {
+            DbLocation::File(path) => UnitRepository::new(path.clone()),
+            DbLocation::Memory => UnitRepository::in_memory(),
+        }
+    }
+
+    pub fn get_connection(&self) -> RepoResult<rusqlite_pool::ConnectionHandle> {
+        self.pool.pop().ok_or(RepoError::NoConnection)
+    }
 
-        let mut units = Vec::new();
+    /// Brings the database schema up to date
+    pub fn migrate(&self) -> RepoResult<()> {
+        let mut conn = self.get_connection()?;
+        run_migrations(&mut conn).map_err(RepoError::Migration)
+    }
 
-        for row in rows {
-            units.push(row.unwrap());
+    pub fn get_units(&self) -> RepoResult<Vec<UnitDefinition>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM units AS u LEFT JOIN unit_versions AS uv ON (u.current_version_id = uv.version_id)",
+            UNIT_COLUMNS
+        ))?;
+
+        let rows = stmt.query_map([], Self::unit_from_row)?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    pub fn get_unit(&self, unit_id: u64) -> RepoResult<UnitDefinition> {
+        let conn = self.get_connection()?;
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM units AS u LEFT JOIN unit_versions AS uv ON (u.current_version_id = uv.version_id)
+                  WHERE u.unit_id = ?1",
+                UNIT_COLUMNS
+            ),
+            [unit_id],
+            Self::unit_from_row,
+        )
+        .optional()?
+        .ok_or(RepoError::UnitNotFound(unit_id))
+    }
+
+    fn unit_from_row(row: &rusqlite::Row) -> rusqlite::Result<UnitDefinition> {
+        Ok(UnitDefinition {
+            unit_id: row.get(0)?,
+            name: row.get(1)?,
+            code: row.get(2)?,
+            current_version_id: row.get(3)?,
+            stats: UnitStats {
+                sprite: row.get(4)?,
+                scale: row.get(5)?,
+                max_speed: row.get(6)?,
+                max_hp: row.get(7)?,
+                cycle_budget: row.get(8)?,
+                devices: InstalledDevices(row.get(9)?),
+                armor: row.get(10)?,
+            },
+        })
+    }
+
+    pub fn update_unit_stats(&self, unit_id: u64, stats: &UnitStats) -> RepoResult<()> {
+        let conn = self.get_connection()?;
+        Self::write_unit_stats(&conn, unit_id, stats)
+    }
+
+    fn write_unit_stats(conn: &Connection, unit_id: u64, stats: &UnitStats) -> RepoResult<()> {
+        let updated = conn.execute(
+            "UPDATE units SET sprite = ?1, scale = ?2, max_speed = ?3, max_hp = ?4,
+              cycle_budget = ?5, devices = ?6, armor = ?7 WHERE unit_id = ?8",
+            (
+                &stats.sprite,
+                stats.scale,
+                stats.max_speed,
+                stats.max_hp,
+                stats.cycle_budget,
+                stats.devices.0,
+                stats.armor,
+                unit_id,
+            ),
+        )?;
+
+        if updated == 0 {
+            return Err(RepoError::UnitNotFound(unit_id));
+        }
+        Ok(())
+    }
+
+    pub fn new_unit_type(&self, name: String) -> RepoResult<u64> {
+        if self.find_unit_by_name(&name)?.is_some() {
+            return Err(RepoError::NameTaken(name));
         }
 
-        units
+        let conn = self.get_connection()?;
+        Ok(conn.query_row(
+            "INSERT INTO units (name) VALUES (?1) RETURNING unit_id",
+            [name],
+            |row| row.get(0),
+        )?)
     }
 
-    pub fn new_unit_type(&self, name: String) {
-        let conn = self.pool.pop().unwrap();
-        conn.execute("INSERT INTO units (name) VALUES (?1)", [name])
-            .unwrap();
+    pub fn rename_unit_type(&self, unit_id: u64, name: String) -> RepoResult<()> {
+        if self.find_unit_by_name(&name)?.is_some_and(|id| id != unit_id) {
+            return Err(RepoError::NameTaken(name));
+        }
+
+        let conn = self.get_connection()?;
+        let updated = conn.execute(
+            "UPDATE units SET name = ?1 WHERE unit_id = ?2",
+            (name, unit_id),
+        )?;
+
+        if updated == 0 {
+            return Err(RepoError::UnitNotFound(unit_id));
+        }
+        Ok(())
     }
 
-    pub fn update_code_for_unit(&self, unit_id: u64, new_code: String) {
-        let conn = self.pool.pop().unwrap();
-        let version_id: u64 = conn
+    /// Creates a new unit type with the stats and current version of an existing one, as a
+    /// starting point. The history and stored blobs stay with the original.
+    pub fn duplicate_unit_type(&self, unit_id: u64, name: String) -> RepoResult<u64> {
+        if self.find_unit_by_name(&name)?.is_some() {
+            return Err(RepoError::NameTaken(name));
+        }
+
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction()?;
+
+        let new_unit_id: u64 = tx
             .query_row(
-                "INSERT INTO unit_versions (unit_id, code) VALUES (?1, ?2) RETURNING version_id",
-                (unit_id, new_code),
+                "INSERT INTO units (name, sprite, scale, max_speed, max_hp, cycle_budget, devices, armor)
+                  SELECT ?1, sprite, scale, max_speed, max_hp, cycle_budget, devices, armor
+                  FROM units WHERE unit_id = ?2 RETURNING unit_id",
+                (&name, unit_id),
                 |row| row.get(0),
             )
-            .unwrap();
+            .optional()?
+            .ok_or(RepoError::UnitNotFound(unit_id))?;
+
+        let version_id: Option<u64> = tx
+            .query_row(
+                "INSERT INTO unit_versions (unit_id, code, rom, symbols, label_sizes, status, build_error)
+                  SELECT ?1, uv.code, uv.rom, uv.symbols, uv.label_sizes, uv.status, uv.build_error
+                  FROM units AS u JOIN unit_versions AS uv ON (u.current_version_id = uv.version_id)
+                  WHERE u.unit_id = ?2 RETURNING version_id",
+                [new_unit_id, unit_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        if let Some(version_id) = version_id {
+            tx.execute(
+                "UPDATE units SET current_version_id = ?1 WHERE unit_id = ?2",
+                [version_id, new_unit_id],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(new_unit_id)
+    }
+
+    /// Deletes a unit type along with every version of its code and everything its units
+    /// stored. Rows are removed in reference order, so it holds with foreign keys enforced.
+    pub fn delete_unit_type(&self, unit_id: u64) -> RepoResult<()> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction()?;
+
+        // The unit type points at its current version, which points back at it
+        let updated = tx.execute(
+            "UPDATE units SET current_version_id = NULL WHERE unit_id = ?1",
+            [unit_id],
+        )?;
+        if updated == 0 {
+            return Err(RepoError::UnitNotFound(unit_id));
+        }
+
+        tx.execute("DELETE FROM unit_storage WHERE unit_id = ?1", [unit_id])?;
+        tx.execute("DELETE FROM unit_instances WHERE unit_id = ?1", [unit_id])?;
+        tx.execute("DELETE FROM unit_versions WHERE unit_id = ?1", [unit_id])?;
+        tx.execute("DELETE FROM units WHERE unit_id = ?1", [unit_id])?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn find_unit_by_name(&self, name: &str) -> RepoResult<Option<u64>> {
+        let conn = self.get_connection()?;
+        Ok(conn
+            .query_row("SELECT unit_id FROM units WHERE name = ?1", [name], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    /// Saves a new version of a unit type's code along with its assembled ROM and makes it
+    /// the current one. Code that doesn't assemble is kept as a broken version, which
+    /// doesn't become current.
+    pub fn update_code_for_unit(&self, unit_id: u64, new_code: String) -> RepoResult<u64> {
+        let (version_id, error) = self.add_version(unit_id, new_code, None)?;
+
+        if let Some(error) = error {
+            return Err(RepoError::BrokenVersion { version_id, error });
+        }
+
+        let conn = self.get_connection()?;
         conn.execute(
             "UPDATE units SET current_version_id = ?1 WHERE unit_id = ?2",
             [version_id, unit_id],
-        )
-        .unwrap();
+        )?;
+        Ok(version_id)
     }
 
-    pub fn get_latest_code_for_unit(&self, unit_id: u64) -> Option<String> {
-        let conn = self.pool.pop().unwrap();
-        conn.query_row(
-            "SELECT code FROM unit_versions WHERE version_id =
-              (SELECT MAX(version_id) FROM unit_versions WHERE unit_id = ?1)",
-            (unit_id,),
-            |row| row.get(0)
-        ).unwrap()
+    /// Saves a version without making it current, assembling it first. Returns its id and
+    /// the assembler error if it's broken. `created_at` defaults to now.
+    pub fn add_version(
+        &self,
+        unit_id: u64,
+        code: String,
+        created_at: Option<&str>,
+    ) -> RepoResult<(u64, Option<String>)> {
+        let conn = self.get_connection()?;
+        Self::insert_version(&conn, unit_id, code, created_at)
+    }
+
+    fn insert_version(
+        conn: &Connection,
+        unit_id: u64,
+        code: String,
+        created_at: Option<&str>,
+    ) -> RepoResult<(u64, Option<String>)> {
+        let artifact = BuildArtifact::build(&code);
+        let version_id: u64 = conn.query_row(
+            "INSERT INTO unit_versions (unit_id, code, rom, symbols, label_sizes, status, build_error, created_at)
+              VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, COALESCE(?8, CURRENT_TIMESTAMP)) RETURNING version_id",
+            (
+                unit_id,
+                code,
+                &artifact.rom,
+                &artifact.symbols,
+                &artifact.label_sizes,
+                artifact.status.as_str(),
+                &artifact.error,
+                created_at,
+            ),
+            |row| row.get(0),
+        )?;
+
+        Ok((version_id, artifact.error))
+    }
+
+    /// Writes an imported unit type in a single transaction, so a failed import leaves
+    /// nothing behind. A new type named `name` is created when `unit_id` is None.
+    /// `versions` are (code, created_at) pairs, oldest first, and the one at `current`
+    /// becomes current if it assembles.
+    pub fn import_unit_type(
+        &self,
+        unit_id: Option<u64>,
+        name: &str,
+        stats: &UnitStats,
+        versions: &[(String, String)],
+        current: Option<usize>,
+    ) -> RepoResult<u64> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction()?;
+
+        let unit_id = match unit_id {
+            Some(unit_id) => unit_id,
+            None => tx.query_row(
+                "INSERT INTO units (name) VALUES (?1) RETURNING unit_id",
+                [name],
+                |row| row.get(0),
+            )?,
+        };
+        Self::write_unit_stats(&tx, unit_id, stats)?;
+
+        for (i, (code, created_at)) in versions.iter().enumerate() {
+            let (version_id, error) =
+                Self::insert_version(&tx, unit_id, code.clone(), Some(created_at))?;
+
+            if current == Some(i) && error.is_none() {
+                tx.execute(
+                    "UPDATE units SET current_version_id = ?1 WHERE unit_id = ?2",
+                    [version_id, unit_id],
+                )?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(unit_id)
+    }
+
+    /// Assembles versions saved before ROMs were stored, returning the version's status
+    fn ensure_built(conn: &Connection, version_id: u64) -> RepoResult<VersionStatus> {
+        let (code, status): (String, String) = conn.query_row(
+            "SELECT code, status FROM unit_versions WHERE version_id = ?1",
+            [version_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let status = VersionStatus::parse(&status);
+        if status != VersionStatus::Unbuilt {
+            return Ok(status);
+        }
+
+        let artifact = BuildArtifact::build(&code);
+        conn.execute(
+            "UPDATE unit_versions SET rom = ?1, symbols = ?2, label_sizes = ?3, status = ?4,
+              build_error = ?5 WHERE version_id = ?6",
+            (
+                &artifact.rom,
+                &artifact.symbols,
+                &artifact.label_sizes,
+                artifact.status.as_str(),
+                &artifact.error,
+                version_id,
+            ),
+        )?;
+        Ok(artifact.status)
+    }
+
+    /// Assembled program of the current version of a unit type, along with the version id
+    pub fn get_current_program(&self, unit_id: u64) -> RepoResult<(u64, Program)> {
+        let conn = self.get_connection()?;
+        let version_id: Option<u64> = conn
+            .query_row(
+                "SELECT current_version_id FROM units WHERE unit_id = ?1",
+                [unit_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(RepoError::UnitNotFound(unit_id))?;
+        let version_id = version_id.ok_or(RepoError::NoCode(unit_id))?;
+        drop(conn);
+
+        Ok((version_id, self.get_version_program(version_id)?))
+    }
+
+    /// Assembled program of a version, failing if the version is broken
+    pub fn get_version_program(&self, version_id: u64) -> RepoResult<Program> {
+        let conn = self.get_connection()?;
+        Self::ensure_built(&conn, version_id)?;
+
+        let (rom, symbols, label_sizes, error): (
+            Option<Vec<u8>>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+        ) = conn.query_row(
+            "SELECT rom, symbols, label_sizes, build_error FROM unit_versions WHERE version_id = ?1",
+            [version_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+
+        let Some(rom) = rom else {
+            return Err(RepoError::BrokenVersion {
+                version_id,
+                error: error.unwrap_or_default(),
+            });
+        };
+        let symbol_table = symbols
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        let label_sizes = label_sizes
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+
+        Ok(Program {
+            rom,
+            symbol_table,
+            label_sizes,
+        })
+    }
+
+    /// Code of the version units of this type are spawned with
+    pub fn get_current_code_for_unit(&self, unit_id: u64) -> RepoResult<String> {
+        let conn = self.get_connection()?;
+        let code: Option<String> = conn
+            .query_row(
+                "SELECT uv.code FROM units AS u
+                  LEFT JOIN unit_versions AS uv ON (u.current_version_id = uv.version_id)
+                  WHERE u.unit_id = ?1",
+                [unit_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(RepoError::UnitNotFound(unit_id))?;
+
+        code.ok_or(RepoError::NoCode(unit_id))
+    }
+
+    /// Every saved version of a unit type's code, newest first
+    pub fn list_versions(&self, unit_id: u64) -> RepoResult<Vec<UnitVersion>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT version_id, unit_id, code, created_at, status, build_error FROM unit_versions
+              WHERE unit_id = ?1 ORDER BY version_id DESC",
+        )?;
+
+        let rows = stmt.query_map([unit_id], Self::version_from_row)?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    pub fn get_version(&self, version_id: u64) -> RepoResult<Option<UnitVersion>> {
+        let conn = self.get_connection()?;
+        Ok(conn
+            .query_row(
+                "SELECT version_id, unit_id, code, created_at, status, build_error FROM unit_versions
+                  WHERE version_id = ?1",
+                [version_id],
+                Self::version_from_row,
+            )
+            .optional()?)
+    }
+
+    fn version_from_row(row: &rusqlite::Row) -> rusqlite::Result<UnitVersion> {
+        Ok(UnitVersion {
+            version_id: row.get(0)?,
+            unit_id: row.get(1)?,
+            code: row.get(2)?,
+            created_at: row.get(3)?,
+            status: VersionStatus::parse(&row.get::<_, String>(4)?),
+            build_error: row.get(5)?,
+        })
+    }
+
+    /// Line diff going from version `from` to version `to`, None if either doesn't exist
+    pub fn diff_versions(&self, from: u64, to: u64) -> RepoResult<Option<Vec<DiffLine>>> {
+        let (Some(from), Some(to)) = (self.get_version(from)?, self.get_version(to)?) else {
+            return Ok(None);
+        };
+        Ok(Some(line_diff(&from.code, &to.code)))
+    }
+
+    /// Makes an existing version the one units of its type run, returning false if the
+    /// version doesn't belong to the unit type. Broken versions can't become current.
+    pub fn set_current_version(&self, unit_id: u64, version_id: u64) -> RepoResult<bool> {
+        let conn = self.get_connection()?;
+        let belongs: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM unit_versions WHERE version_id = ?1 AND unit_id = ?2)",
+            [version_id, unit_id],
+            |row| row.get(0),
+        )?;
+        if !belongs {
+            return Ok(false);
+        }
+
+        if Self::ensure_built(&conn, version_id)? == VersionStatus::Broken {
+            let error: Option<String> = conn.query_row(
+                "SELECT build_error FROM unit_versions WHERE version_id = ?1",
+                [version_id],
+                |row| row.get(0),
+            )?;
+            return Err(RepoError::BrokenVersion {
+                version_id,
+                error: error.unwrap_or_default(),
+            });
+        }
+
+        let updated = conn.execute(
+            "UPDATE units SET current_version_id = ?1 WHERE unit_id = ?2
+              AND EXISTS (SELECT 1 FROM unit_versions WHERE version_id = ?1 AND unit_id = ?2)",
+            [version_id, unit_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Reads a blob from unit storage. `instance_key` is the unit's `InstanceKey`, or 0 for
+    /// blobs shared by the unit type.
+    pub fn read_blob(
+        &self,
+        unit_id: u64,
+        instance_key: u64,
+        name: &str,
+    ) -> RepoResult<Option<Vec<u8>>> {
+        let conn = self.get_connection()?;
+        Ok(conn
+            .query_row(
+                "SELECT data FROM unit_storage WHERE unit_id = ?1 AND instance_id = ?2 AND name = ?3",
+                (unit_id, instance_key, name),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Writes a blob to unit storage, returning false if it would go over the quotas
+    pub fn write_blob(
+        &self,
+        unit_id: u64,
+        instance_key: u64,
+        name: &str,
+        data: &[u8],
+        append: bool,
+    ) -> RepoResult<bool> {
+        let mut blob = if append {
+            self.read_blob(unit_id, instance_key, name)?.unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        blob.extend_from_slice(data);
+
+        if blob.len() > MAX_BLOB_SIZE {
+            return Ok(false);
+        }
+
+        let conn = self.get_connection()?;
+        let (count, exists): (i64, bool) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(name = ?3), 0) > 0 FROM unit_storage
+              WHERE unit_id = ?1 AND instance_id = ?2",
+            (unit_id, instance_key, name),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        if !exists && count as usize >= MAX_BLOBS_PER_SCOPE {
+            return Ok(false);
+        }
+
+        conn.execute(
+            "INSERT INTO unit_storage (unit_id, instance_id, name, data) VALUES (?1, ?2, ?3, ?4)
+              ON CONFLICT (unit_id, instance_id, name)
+              DO UPDATE SET data = excluded.data, updated_at = CURRENT_TIMESTAMP",
+            (unit_id, instance_key, name, blob),
+        )?;
+
+        Ok(true)
+    }
+
+    /// Deletes a blob from unit storage, returning whether it existed
+    pub fn delete_blob(&self, unit_id: u64, instance_key: u64, name: &str) -> RepoResult<bool> {
+        let conn = self.get_connection()?;
+        let deleted = conn.execute(
+            "DELETE FROM unit_storage WHERE unit_id = ?1 AND instance_id = ?2 AND name = ?3",
+            (unit_id, instance_key, name),
+        )?;
+        Ok(deleted > 0)
+    }
+
+    /// Hands out a new instance key for a unit of type `unit_id`. Keys are never reused, so
+    /// a unit's own storage can't be picked up by another unit.
+    pub fn new_instance_key(&self, unit_id: u64) -> RepoResult<u64> {
+        let conn = self.get_connection()?;
+        Ok(conn.query_row(
+            "INSERT INTO unit_instances (unit_id) VALUES (?1) RETURNING instance_key",
+            [unit_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Marks a key handed out elsewhere (e.g. by the database a saved world came from) as
+    /// taken, so it isn't handed out again
+    pub fn claim_instance_key(&self, instance_key: u64, unit_id: u64) -> RepoResult<()> {
+        let conn = self.get_connection()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO unit_instances (instance_key, unit_id) VALUES (?1, ?2)",
+            [instance_key, unit_id],
+        )?;
+        Ok(())
+    }
+
+    /// Every blob a single unit stored for itself, by name
+    pub fn instance_blobs(&self, unit_id: u64, instance_key: u64) -> RepoResult<Vec<(String, Vec<u8>)>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT name, data FROM unit_storage WHERE unit_id = ?1 AND instance_id = ?2
+              ORDER BY name",
+        )?;
+
+        let rows = stmt.query_map([unit_id, instance_key], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Replaces everything a single unit stored for itself with `blobs`
+    pub fn replace_instance_blobs(
+        &self,
+        unit_id: u64,
+        instance_key: u64,
+        blobs: &[(String, Vec<u8>)],
+    ) -> RepoResult<()> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM unit_storage WHERE unit_id = ?1 AND instance_id = ?2",
+            [unit_id, instance_key],
+        )?;
+        for (name, data) in blobs {
+            tx.execute(
+                "INSERT INTO unit_storage (unit_id, instance_id, name, data) VALUES (?1, ?2, ?3, ?4)",
+                (unit_id, instance_key, name, data),
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
     }
 }
 
-fn run_migrations(conn: &mut Connection) {
-    let migrations = Migrations::new(vec![M::up(
-        r#"
+fn run_migrations(conn: &mut Connection) -> Result<(), rusqlite_migration::Error> {
+    let migrations = Migrations::new(vec![
+        M::up(
+            r#"
             -- Units table - stores basic unit information and tracks current version
             CREATE TABLE units (
                 unit_id INTEGER PRIMARY KEY,
@@ -108,20 +827,308 @@ fn run_migrations(conn: &mut Connection) {
                 FOREIGN KEY (unit_id) REFERENCES units (unit_id)
             );
         "#,
-    )]);
+        ),
+        M::up(
+            r#"
+            -- Unit storage table - named blobs unit programs keep through the Storage device
+            CREATE TABLE unit_storage (
+                unit_id INTEGER NOT NULL,
+                instance_id INTEGER NOT NULL DEFAULT 0,
+                name TEXT NOT NULL,
+                data BLOB NOT NULL,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (unit_id, instance_id, name),
+                FOREIGN KEY (unit_id) REFERENCES units (unit_id)
+            );
+        "#,
+        ),
+        M::up(
+            r#"
+            -- Assembled program of each version, so units can be spawned without assembling
+            ALTER TABLE unit_versions ADD COLUMN rom BLOB;
+            ALTER TABLE unit_versions ADD COLUMN symbols TEXT;
+            ALTER TABLE unit_versions ADD COLUMN status TEXT NOT NULL DEFAULT 'unbuilt';
+            ALTER TABLE unit_versions ADD COLUMN build_error TEXT;
+        "#,
+        ),
+        M::up(
+            r#"
+            -- Unit type metadata - looks, stats and which devices units of the type have
+            ALTER TABLE units ADD COLUMN sprite TEXT NOT NULL DEFAULT 'medusa';
+            ALTER TABLE units ADD COLUMN scale REAL NOT NULL DEFAULT 2.0;
+            ALTER TABLE units ADD COLUMN max_speed REAL NOT NULL DEFAULT 10.0;
+            ALTER TABLE units ADD COLUMN max_hp INTEGER NOT NULL DEFAULT 100;
+            ALTER TABLE units ADD COLUMN cycle_budget INTEGER NOT NULL DEFAULT 1000;
+            ALTER TABLE units ADD COLUMN devices INTEGER NOT NULL DEFAULT 65535;
+        "#,
+        ),
+        M::up(
+            r#"
+            -- How much memory each label declares, for reloads that keep unit state.
+            -- Versions built before this get built again the next time they're needed.
+            ALTER TABLE unit_versions ADD COLUMN label_sizes TEXT;
+            UPDATE unit_versions SET rom = NULL, symbols = NULL, status = 'unbuilt'
+              WHERE status = 'ok';
+        "#,
+        ),
+        M::up(
+            r#"
+            -- Instance keys of every unit ever spawned. Per-unit storage is keyed on these
+            -- rather than the in-world handles, which start over every session.
+            CREATE TABLE unit_instances (
+                instance_key INTEGER PRIMARY KEY AUTOINCREMENT,
+                unit_id INTEGER NOT NULL
+            );
 
-    migrations.to_latest(conn).unwrap();
-    println!("MIGRATIONS RAN");
-}
+            -- Per-unit blobs stored so far are keyed on handles and can't be told apart
+            DELETE FROM unit_storage WHERE instance_id != 0;
+        "#,
+        ),
+        M::up(
+            r#"
+            -- Armor of each unit type, flat damage reduction
+            ALTER TABLE units ADD COLUMN armor INTEGER NOT NULL DEFAULT 0;
+        "#,
+        ),
+    ]);
 
-fn setup_database(unit_repository: ResMut<UnitRepository>) {
-    let mut conn = unit_repository.get_connection().unwrap();
-    run_migrations(&mut conn);
+    migrations.to_latest(conn)?;
+    println!("MIGRATIONS RAN");
+    Ok(())
 }
 
 impl Plugin for UnitRepoPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(UnitRepository::new("./units.db"))
-            .add_systems(Startup, setup_database);
+        let workspaces = app
+            .world()
+            .get_resource::<Workspaces>()
+            .cloned()
+            .unwrap_or_else(|| Workspaces::from_args(&mut Vec::new()));
+        let repo = workspaces
+            .open(&workspaces.current)
+            .expect("couldn't open the unit database");
+
+        app.insert_resource(repo)
+            .insert_resource(workspaces)
+            .add_event::<SwitchWorkspace>()
+            .add_event::<WorkspaceSwitchFailed>()
+            .add_systems(PreUpdate, switch_workspace);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::devices::FactoryPorts;
+    use raven_uxn::Ports;
+
+    pub(crate) fn test_repository() -> UnitRepository {
+        let repo = UnitRepository::in_memory();
+        repo.migrate().unwrap();
+        repo
+    }
+
+    #[test]
+    fn test_blob_storage_scopes_and_quotas() {
+        let repo = test_repository();
+        repo.new_unit_type("scout".to_string()).unwrap();
+
+        assert!(repo.write_blob(1, 0, "waypoints", &[1, 2], false).unwrap());
+        assert!(repo.write_blob(1, 0, "waypoints", &[3], true).unwrap());
+        assert_eq!(repo.read_blob(1, 0, "waypoints").unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(repo.read_blob(1, 7, "waypoints").unwrap(), None);
+
+        assert!(!repo
+            .write_blob(1, 7, "big", &vec![0; MAX_BLOB_SIZE + 1], false)
+            .unwrap());
+        for i in 0..MAX_BLOBS_PER_SCOPE {
+            assert!(repo
+                .write_blob(1, 7, &format!("blob-{}", i), &[0], false)
+                .unwrap());
+        }
+        assert!(!repo.write_blob(1, 7, "one-too-many", &[0], false).unwrap());
+        assert!(repo.write_blob(1, 7, "blob-0", &[1], false).unwrap());
+
+        assert!(repo.delete_blob(1, 0, "waypoints").unwrap());
+        assert_eq!(repo.read_blob(1, 0, "waypoints").unwrap(), None);
+    }
+
+    #[test]
+    fn test_instance_storage_persists_per_key() {
+        let repo = test_repository();
+        repo.new_unit_type("scout".to_string()).unwrap();
+
+        let first = repo.new_instance_key(1).unwrap();
+        assert!(repo.write_blob(1, first, "home", &[2], false).unwrap());
+        assert!(repo.write_blob(1, 0, "shared", &[1], false).unwrap());
+
+        // A unit spawned later, e.g. in another session, gets a key of its own
+        let second = repo.new_instance_key(1).unwrap();
+        assert_ne!(second, first);
+        assert_eq!(repo.read_blob(1, second, "home").unwrap(), None);
+        assert_eq!(repo.read_blob(1, first, "home").unwrap(), Some(vec![2]));
+
+        // Keys claimed from a saved world aren't handed out again
+        repo.claim_instance_key(second + 10, 1).unwrap();
+        assert!(repo.new_instance_key(1).unwrap() > second + 10);
+
+        let blobs = vec![("route".to_string(), vec![7, 8])];
+        repo.replace_instance_blobs(1, first, &blobs).unwrap();
+        assert_eq!(repo.instance_blobs(1, first).unwrap(), blobs);
+        assert_eq!(repo.read_blob(1, 0, "shared").unwrap(), Some(vec![1]));
+    }
+
+    #[test]
+    fn test_version_history_and_restore() {
+        let repo = test_repository();
+        repo.new_unit_type("scout".to_string()).unwrap();
+        repo.new_unit_type("miner".to_string()).unwrap();
+        repo.update_code_for_unit(1, "BRK".to_string()).unwrap();
+        repo.update_code_for_unit(1, "#01 POP\nBRK".to_string()).unwrap();
+        repo.update_code_for_unit(2, "BRK".to_string()).unwrap();
+
+        let versions = repo.list_versions(1).unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version_id).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert_eq!(repo.get_version(1).unwrap().unwrap().code, "BRK");
+        assert_eq!(repo.get_version(4).unwrap(), None);
+
+        assert_eq!(
+            repo.diff_versions(1, 2).unwrap(),
+            Some(vec![
+                DiffLine::Added("#01 POP".to_string()),
+                DiffLine::Same("BRK".to_string()),
+            ])
+        );
+
+        assert!(!repo.set_current_version(1, 3).unwrap());
+        assert!(repo.set_current_version(1, 1).unwrap());
+        let scout = repo
+            .get_units()
+            .unwrap()
+            .into_iter()
+            .find(|u| u.unit_id == 1)
+            .unwrap();
+        assert_eq!(scout.current_version_id, Some(1));
+        assert_eq!(scout.code.as_deref(), Some("BRK"));
+    }
+
+    #[test]
+    fn test_current_code_follows_current_version() {
+        let repo = test_repository();
+        let unit_id = repo.new_unit_type("scout".to_string()).unwrap();
+
+        assert!(matches!(
+            repo.get_current_code_for_unit(unit_id),
+            Err(RepoError::NoCode(_))
+        ));
+        assert!(matches!(
+            repo.get_current_code_for_unit(unit_id + 1),
+            Err(RepoError::UnitNotFound(_))
+        ));
+
+        let first = repo.update_code_for_unit(unit_id, "BRK".to_string()).unwrap();
+        repo.update_code_for_unit(unit_id, "#01 POP BRK".to_string())
+            .unwrap();
+        assert_eq!(repo.get_current_code_for_unit(unit_id).unwrap(), "#01 POP BRK");
+
+        repo.set_current_version(unit_id, first).unwrap();
+        assert_eq!(repo.get_current_code_for_unit(unit_id).unwrap(), "BRK");
+    }
+
+    #[test]
+    fn test_unit_stats_round_trip() {
+        let repo = test_repository();
+        let unit_id = repo.new_unit_type("tank".to_string()).unwrap();
+        assert_eq!(repo.get_unit(unit_id).unwrap().stats, UnitStats::default());
+
+        let mut devices = InstalledDevices::ALL;
+        devices.set(FactoryPorts::BASE, false);
+        let stats = UnitStats {
+            sprite: "golem".to_string(),
+            scale: 3.0,
+            max_speed: 4.5,
+            max_hp: 400,
+            armor: 5,
+            cycle_budget: 250,
+            devices,
+        };
+        repo.update_unit_stats(unit_id, &stats).unwrap();
+
+        assert_eq!(repo.get_unit(unit_id).unwrap().stats, stats);
+        assert!(matches!(
+            repo.update_unit_stats(unit_id + 1, &stats),
+            Err(RepoError::UnitNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_broken_versions_never_become_current() {
+        let repo = test_repository();
+        let unit_id = repo.new_unit_type("scout".to_string()).unwrap();
+
+        let good = repo.update_code_for_unit(unit_id, "|100 BRK".to_string()).unwrap();
+        let broken = match repo.update_code_for_unit(unit_id, "|100 #123 BRK".to_string()) {
+            Err(RepoError::BrokenVersion { version_id, .. }) => version_id,
+            other => panic!("expected a broken version, got {:?}", other),
+        };
+
+        let (version_id, program) = repo.get_current_program(unit_id).unwrap();
+        assert_eq!(version_id, good);
+        assert_eq!(program.rom, vec![0x00]);
+
+        let version = repo.get_version(broken).unwrap().unwrap();
+        assert_eq!(version.status, VersionStatus::Broken);
+        assert!(version.build_error.is_some());
+        assert!(matches!(
+            repo.set_current_version(unit_id, broken),
+            Err(RepoError::BrokenVersion { .. })
+        ));
+        assert_eq!(repo.get_current_program(unit_id).unwrap().0, good);
+    }
+
+    #[test]
+    fn test_rename_duplicate_delete() {
+        let repo = test_repository();
+        let scout = repo.new_unit_type("scout".to_string()).unwrap();
+        let tank = repo.new_unit_type("tank".to_string()).unwrap();
+        repo.update_code_for_unit(scout, "|100 BRK".to_string()).unwrap();
+        repo.update_code_for_unit(scout, "|100 #01 POP BRK".to_string()).unwrap();
+        repo.write_blob(scout, 0, "map", &[1, 2, 3], false).unwrap();
+
+        assert!(matches!(
+            repo.rename_unit_type(scout, "tank".to_string()),
+            Err(RepoError::NameTaken(_))
+        ));
+        repo.rename_unit_type(scout, "ranger".to_string()).unwrap();
+        assert_eq!(repo.get_unit(scout).unwrap().name, "ranger");
+
+        let copy = repo.duplicate_unit_type(scout, "ranger 2".to_string()).unwrap();
+        assert_eq!(repo.get_current_code_for_unit(copy).unwrap(), "|100 #01 POP BRK");
+        assert_eq!(repo.list_versions(copy).unwrap().len(), 1);
+        assert_eq!(repo.read_blob(copy, 0, "map").unwrap(), None);
+        let empty = repo.duplicate_unit_type(tank, "tank 2".to_string()).unwrap();
+        assert!(matches!(
+            repo.get_current_code_for_unit(empty),
+            Err(RepoError::NoCode(_))
+        ));
+
+        repo.delete_unit_type(scout).unwrap();
+        assert!(matches!(repo.get_unit(scout), Err(RepoError::UnitNotFound(_))));
+        assert!(repo.list_versions(scout).unwrap().is_empty());
+        assert_eq!(repo.read_blob(scout, 0, "map").unwrap(), None);
+        assert!(matches!(
+            repo.delete_unit_type(scout),
+            Err(RepoError::UnitNotFound(_))
+        ));
+        assert_eq!(repo.get_current_code_for_unit(copy).unwrap(), "|100 #01 POP BRK");
+
+        let conn = repo.get_connection().unwrap();
+        let violations: i64 = conn
+            .query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(violations, 0);
     }
 }
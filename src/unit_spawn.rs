@@ -1,48 +1,134 @@
-use crate::bundles::UnitBundle;
-use crate::tools::assembler::assemble;
+use crate::bundles::{UnitBundle, UnitSpawn};
+use crate::components::{Executable, InstanceKey, NextInstanceId};
 use crate::assets::AssetLibrary;
-use crate::unit_repo::UnitRepository;
+use crate::random::WorldSeed;
+use crate::timer::SimulationTick;
+use crate::unit_repo::{UnitRepository, DEFAULT_SPRITE};
 use bevy::prelude::*;
 
 #[derive(Event)]
 pub struct SpawnUnitRequest {
     pub unit_id: u64,
+    pub team: u8,
     pub position: Vec2,
+    /// Unit whose Factory built this one, if any
+    pub producer: Option<Entity>,
+}
+
+/// Sent when a spawn request can't be fulfilled, e.g. the unit type has no code yet
+#[derive(Event, Debug)]
+pub struct UnitSpawnFailed {
+    pub unit_id: u64,
+    pub reason: String,
+    /// Unit whose Factory paid for the spawn, if any
+    pub producer: Option<Entity>,
+}
+
+/// Sent after a unit type is deleted from the repository, its units on the map go with it
+#[derive(Event, Debug)]
+pub struct UnitTypeDeleted {
+    pub unit_id: u64,
 }
 
 pub struct UnitSpawnPlugin;
 
+/// Sprite for a unit type's sprite name, medusa if the name isn't in the sprite sheet
+pub fn unit_sprite(asset_lib: &AssetLibrary, name: &str) -> Sprite {
+    let asset = &asset_lib.assets["roguelike"];
+
+    Sprite::from_atlas_image(
+        asset.image.clone(),
+        TextureAtlas {
+            layout: asset.layout.clone(),
+            index: asset
+                .mappings
+                .get(name)
+                .copied()
+                .unwrap_or_else(|| asset.mappings[DEFAULT_SPRITE]),
+        }
+    )
+}
+
 fn unit_spawner(
     mut spawn_events: EventReader<SpawnUnitRequest>,
     mut commands: Commands,
     repo: Res<UnitRepository>,
-    asset_lib: Res<AssetLibrary>,
+    (asset_lib, seed, tick): (Res<AssetLibrary>, Res<WorldSeed>, Res<SimulationTick>),
+    mut next_instance_id: ResMut<NextInstanceId>,
+    mut producers: Query<&mut Executable>,
+    mut failures: EventWriter<UnitSpawnFailed>,
 ) {
     for request in spawn_events.read() {
-        let program_code = repo.get_latest_code_for_unit(request.unit_id).unwrap();
-        let program = assemble(program_code).unwrap();
-        let asset = &asset_lib.assets["roguelike"];
-
-        let sprite = Sprite::from_atlas_image(
-            asset.image.clone(),
-            TextureAtlas {
-                layout: asset.layout.clone(),
-                index: asset.mappings["medusa"]
+        let current = repo.get_unit(request.unit_id).and_then(|unit| {
+            let (version_id, program) = repo.get_current_program(request.unit_id)?;
+            let instance_key = repo.new_instance_key(request.unit_id)?;
+            Ok((unit, version_id, program, instance_key))
+        });
+
+        let (unit, version_id, program, instance_key) = match current {
+            Ok(current) => current,
+            Err(e) => {
+                let reason = e.to_string();
+                eprintln!("Couldn't spawn unit type {}: {}", request.unit_id, reason);
+                failures.send(UnitSpawnFailed {
+                    unit_id: request.unit_id,
+                    reason,
+                    producer: request.producer,
+                });
+                continue;
             }
-        );
+        };
+        let sprite = unit_sprite(&asset_lib, &unit.stats.sprite);
+        let instance_id = next_instance_id.next();
 
         commands.spawn(UnitBundle::new(
             request.unit_id,
-            request.position,
-            sprite,
             &program,
+            sprite,
+            &unit.stats,
+            UnitSpawn {
+                instance_id,
+                instance_key: InstanceKey(instance_key),
+                team: request.team,
+                pos: request.position,
+                spawn_tick: tick.0,
+                version_id: Some(version_id),
+            },
+            seed.0,
         ));
+
+        if let Some(Ok(mut producer)) = request.producer.map(|p| producers.get_mut(p)) {
+            let Executable { cpu, device, .. } = &mut *producer;
+            device.factory.set_child(cpu, instance_id.0);
+
+            let create_vec = producer.create_vector();
+            if create_vec != 0 {
+                producer.vector_queue.push(create_vec);
+            }
+        }
+    }
+}
+
+fn despawn_deleted_units(
+    mut deleted: EventReader<UnitTypeDeleted>,
+    mut commands: Commands,
+    units: Query<(Entity, &Executable)>,
+) {
+    for event in deleted.read() {
+        for (entity, executable) in &units {
+            if executable.unit_type_id == event.unit_id {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
     }
 }
 
 impl Plugin for UnitSpawnPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SpawnUnitRequest>()
-            .add_systems(PostUpdate, unit_spawner);
+            .add_event::<UnitSpawnFailed>()
+            .add_event::<UnitTypeDeleted>()
+            .init_resource::<NextInstanceId>()
+            .add_systems(PostUpdate, (despawn_deleted_units, unit_spawner));
     }
 }
@@ -0,0 +1,245 @@
+//! Where the unit database lives.
+//!
+//! The database path comes from the `--db` flag, the `KIKAI_DB` environment variable or
+//! the `db_path` entry of the settings file, in that order, defaulting to `./units.db`.
+//! Passing `:memory:` keeps everything in memory. That database is the `default`
+//! workspace; other named workspaces are databases in the workspaces directory, and the
+//! one to start in is picked with `--workspace`, `KIKAI_WORKSPACE` or the `workspace`
+//! setting. The settings file is `./kikai.json` unless `KIKAI_SETTINGS` points elsewhere.
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use crate::bundles::unit::Unit;
+use crate::unit_repo::{RepoResult, UnitRepository, DEFAULT_DB_PATH};
+
+pub const DB_PATH_ENV: &str = "KIKAI_DB";
+pub const WORKSPACE_ENV: &str = "KIKAI_WORKSPACE";
+pub const SETTINGS_ENV: &str = "KIKAI_SETTINGS";
+pub const DEFAULT_SETTINGS_FILE: &str = "./kikai.json";
+pub const DEFAULT_WORKSPACES_DIR: &str = "./workspaces";
+pub const DEFAULT_WORKSPACE: &str = "default";
+/// Database path that keeps the repository in memory
+pub const MEMORY_DB: &str = ":memory:";
+
+#[derive(Deserialize, Default)]
+struct Settings {
+    db_path: Option<String>,
+    workspaces_dir: Option<String>,
+    workspace: Option<String>,
+}
+
+impl Settings {
+    fn load() -> Self {
+        let path = std::env::var(SETTINGS_ENV).unwrap_or_else(|_| DEFAULT_SETTINGS_FILE.to_string());
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Settings::default();
+        };
+
+        serde_json::from_str(&contents)
+            .inspect_err(|e| eprintln!("Ignoring settings file {}: {}", path, e))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DbLocation {
+    File(PathBuf),
+    Memory,
+}
+
+impl DbLocation {
+    fn from_path(path: String) -> Self {
+        if path == MEMORY_DB {
+            DbLocation::Memory
+        } else {
+            DbLocation::File(path.into())
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct Workspaces {
+    /// Database of the default workspace
+    pub default_location: DbLocation,
+    /// Where the databases of the other workspaces live
+    pub dir: PathBuf,
+    pub current: String,
+}
+
+/// Takes `--flag value` out of `args`, returning the value
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let i = args.iter().position(|a| a == flag)?;
+    args.remove(i);
+    (i < args.len()).then(|| args.remove(i))
+}
+
+impl Workspaces {
+    /// Works out the configuration from the command line, environment and settings file,
+    /// removing the flags it understands from `args`
+    pub fn from_args(args: &mut Vec<String>) -> Self {
+        let settings = Settings::load();
+
+        let db_path = take_flag(args, "--db")
+            .or_else(|| std::env::var(DB_PATH_ENV).ok())
+            .or(settings.db_path)
+            .unwrap_or_else(|| DEFAULT_DB_PATH.to_string());
+
+        let current = take_flag(args, "--workspace")
+            .or_else(|| std::env::var(WORKSPACE_ENV).ok())
+            .or(settings.workspace)
+            .filter(|name| is_valid_name(name))
+            .unwrap_or_else(|| DEFAULT_WORKSPACE.to_string());
+
+        Workspaces {
+            default_location: DbLocation::from_path(db_path),
+            dir: settings
+                .workspaces_dir
+                .unwrap_or_else(|| DEFAULT_WORKSPACES_DIR.to_string())
+                .into(),
+            current,
+        }
+    }
+
+    /// Every workspace is kept in memory, for tests and headless runs
+    pub fn in_memory() -> Self {
+        Workspaces {
+            default_location: DbLocation::Memory,
+            dir: PathBuf::new(),
+            current: DEFAULT_WORKSPACE.to_string(),
+        }
+    }
+
+    /// Where a workspace's database lives. With an in-memory default every workspace is
+    /// in memory too, and starts out empty each time it's opened.
+    pub fn location(&self, name: &str) -> DbLocation {
+        if name == DEFAULT_WORKSPACE || self.default_location == DbLocation::Memory {
+            self.default_location.clone()
+        } else {
+            DbLocation::File(self.dir.join(format!("{}.db", name)))
+        }
+    }
+
+    pub fn current_location(&self) -> DbLocation {
+        self.location(&self.current)
+    }
+
+    /// Names of the existing workspaces, the default one first
+    pub fn list(&self) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "db" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .filter(|name| name != DEFAULT_WORKSPACE && is_valid_name(name))
+            .collect();
+        names.sort();
+        names.insert(0, DEFAULT_WORKSPACE.to_string());
+
+        if !names.contains(&self.current) {
+            names.push(self.current.clone());
+        }
+        names
+    }
+
+    /// Opens a workspace's database, creating and migrating it if needed
+    pub fn open(&self, name: &str) -> RepoResult<UnitRepository> {
+        let location = self.location(name);
+        if let DbLocation::File(path) = &location {
+            if let Some(parent) = path.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+        }
+
+        let repo = UnitRepository::open(&location);
+        repo.migrate()?;
+        Ok(repo)
+    }
+}
+
+/// Workspace names end up in file names, so they're kept to letters, digits, - and _
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Asks to switch to a workspace, creating it if it doesn't exist
+#[derive(Event)]
+pub struct SwitchWorkspace(pub String);
+
+/// Sent when the requested workspace couldn't be opened, the current one stays in use
+#[derive(Event, Debug)]
+pub struct WorkspaceSwitchFailed {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Opens the requested workspace and replaces the repository with it. Units on the map
+/// belong to the previous workspace's unit types, so they're removed.
+pub fn switch_workspace(
+    mut events: EventReader<SwitchWorkspace>,
+    mut commands: Commands,
+    mut workspaces: ResMut<Workspaces>,
+    units: Query<Entity, With<Unit>>,
+    mut failures: EventWriter<WorkspaceSwitchFailed>,
+) {
+    let Some(SwitchWorkspace(name)) = events.read().last() else {
+        return;
+    };
+
+    if !is_valid_name(name) || *name == workspaces.current {
+        return;
+    }
+
+    match workspaces.open(name) {
+        Ok(repo) => {
+            commands.insert_resource(repo);
+            workspaces.current = name.clone();
+            for entity in &units {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        Err(e) => {
+            failures.send(WorkspaceSwitchFailed {
+                name: name.clone(),
+                reason: e.to_string(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_locations() {
+        let mut args: Vec<String> = ["kikai-rs", "--db", "test.db", "--workspace", "ai", "export"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        let workspaces = Workspaces::from_args(&mut args);
+        assert_eq!(args, vec!["kikai-rs", "export"]);
+        assert_eq!(workspaces.current, "ai");
+        assert_eq!(
+            workspaces.location(DEFAULT_WORKSPACE),
+            DbLocation::File("test.db".into())
+        );
+        assert_eq!(
+            workspaces.current_location(),
+            DbLocation::File(workspaces.dir.join("ai.db"))
+        );
+        assert_eq!(Workspaces::in_memory().location("ai"), DbLocation::Memory);
+
+        assert!(is_valid_name("team-2_test"));
+        assert!(!is_valid_name("../units"));
+        assert!(!is_valid_name(""));
+    }
+}
@@ -0,0 +1,962 @@
+//! Saving the whole world to a file and loading it back, so experiments can be paused.
+//!
+//! A save holds every unit's VM (RAM, device page, both stacks, pc), its debugger
+//! breakpoints, pending vectors and timers, where it is and how it's doing, along with the
+//! program it runs so it comes back even if its unit type changed since. The device state
+//! kept outside the VM comes along too: Interface widgets, the Factory order and job, the
+//! Weapon cooldown and pending shot, Console lines, Sensor results and the Radio inbox and
+//! listen set, and so do the blobs each unit stored for itself under its instance key.
+//! Depots, resource nodes, team resources, the simulation tick and the instance id counter
+//! are saved as well, along with each unit's place in its Random device stream and the tick
+//! it was spawned at. Loading replaces every unit, depot and resource node on the map.
+//! Radio packets in flight are lost.
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::assets::AssetLibrary;
+use crate::bundles::unit::Unit;
+use crate::bundles::{UnitBundle, UnitSpawn};
+use crate::components::executable::VmSnapshot;
+use crate::components::{
+    Depot, Executable, Health, InstanceId, InstanceKey, Inventory, MaxSpeed, NextInstanceId, ResourceNode,
+    Team,
+};
+use crate::devices::console::ConsoleLine;
+use crate::devices::factory::{BuildJob, BuildOrder};
+use crate::devices::interface::{Widget, WidgetKind};
+use crate::devices::radio::ReceivedPacket;
+use crate::devices::sensor::ScanResult;
+use crate::devices::timer::ScheduledVector;
+use crate::devices::weapon::ShotTarget;
+use crate::devices::{InstalledDevices, UnitIO};
+use crate::economy::{SpawnDepotRequest, TeamResources};
+use crate::timer::SimulationTick;
+use crate::tools::assembler::Program;
+use crate::unit_repo::{RepoResult, UnitRepository, UnitStats};
+use crate::unit_spawn::unit_sprite;
+
+/// Bumped whenever the save format changes in a way older builds can't read
+pub const SAVE_FORMAT_VERSION: u32 = 4;
+pub const DEFAULT_SAVE_PATH: &str = "./world.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SavedTimer {
+    pub vector: u16,
+    pub fire_at: u64,
+    pub period: u16,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedWidget {
+    pub id: u8,
+    /// Same codes as the Interface `kind` port
+    pub kind: u8,
+    pub text: String,
+    pub value: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SavedBuildOrder {
+    pub unit_type: u16,
+    pub dx: i16,
+    pub dy: i16,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SavedBuildJob {
+    pub order: SavedBuildOrder,
+    pub ticks_left: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SavedShot {
+    Handle(u16),
+    Coords(u16, u16),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedConsoleLine {
+    pub text: String,
+    pub is_error: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SavedScanResult {
+    pub dx: i16,
+    pub dy: i16,
+    pub kind: u8,
+    pub unit_type: u16,
+    pub friend: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedPacket {
+    pub payload: Vec<u8>,
+    pub frequency: u8,
+    pub sender: u16,
+    pub rssi: u8,
+    pub hops: u8,
+}
+
+/// Device state kept on the host side rather than in the VM's memory
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SavedDeviceState {
+    pub widgets: Vec<SavedWidget>,
+    pub factory_order: Option<SavedBuildOrder>,
+    pub factory_job: Option<SavedBuildJob>,
+    pub factory_refund_due: bool,
+    pub weapon_cooldown_left: u8,
+    pub weapon_pending_shot: Option<SavedShot>,
+    pub console_lines: Vec<SavedConsoleLine>,
+    /// Unfinished standard output and error lines
+    pub console_partial: (String, String),
+    pub sensor_pending_scan: Option<u16>,
+    pub sensor_results: Vec<SavedScanResult>,
+    pub radio_inbox: Vec<SavedPacket>,
+    pub radio_extra_frequencies: Vec<u8>,
+    pub radio_scanning: bool,
+    /// Missing from format 3 saves and earlier, whose units start again from a zero state
+    #[serde(default)]
+    pub random_state: u64,
+    #[serde(default)]
+    pub spawn_tick: u64,
+}
+
+/// A blob the unit stored for itself through the Storage device
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedBlob {
+    pub name: String,
+    /// Base64 encoded contents
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedUnit {
+    pub unit_type_id: u64,
+    pub version_id: Option<u64>,
+    pub instance_id: u16,
+    pub team: u8,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub hp: u16,
+    pub max_hp: u16,
+    pub armor: u16,
+    pub max_speed: f32,
+    pub carried: u16,
+    pub capacity: u16,
+    pub installed_devices: u16,
+    pub cycle_budget: u32,
+    pub cycles_left: u32,
+    pub out_of_cycles: bool,
+    /// Hop count of the packet being handled, None in older saves
+    #[serde(default)]
+    pub handled_hops: Option<u8>,
+    pub pc: Option<u16>,
+    pub breakpoints: Vec<u16>,
+    pub vector_queue: Vec<u16>,
+    pub timers: Vec<Option<SavedTimer>>,
+    /// Base64 encoded RAM
+    pub ram: String,
+    /// Base64 encoded device page
+    pub devices: String,
+    /// Working stack, bottom first
+    pub stack: Vec<u8>,
+    /// Return stack, bottom first
+    pub ret: Vec<u8>,
+    /// Base64 encoded ROM of the program the unit runs
+    pub rom: String,
+    pub symbols: BTreeMap<String, u16>,
+    #[serde(default)]
+    pub label_sizes: BTreeMap<String, u16>,
+    /// Missing from format 1 saves, whose units come back with fresh devices
+    #[serde(default)]
+    pub device_state: SavedDeviceState,
+    /// 0 in format 2 saves and earlier, whose units get a new key and no storage of their own
+    #[serde(default)]
+    pub instance_key: u64,
+    #[serde(default)]
+    pub storage: Vec<SavedBlob>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SavedDepot {
+    pub team: u8,
+    pub position: [f32; 2],
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SavedResourceNode {
+    pub translation: [f32; 3],
+    pub scale: [f32; 3],
+    pub amount: u16,
+    /// Index of the node's sprite in the roguelike atlas
+    pub sprite: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WorldSave {
+    pub format_version: u32,
+    pub tick: u64,
+    pub next_instance_id: u16,
+    pub units: Vec<SavedUnit>,
+    pub depots: Vec<SavedDepot>,
+    /// Missing from format 1 saves, loading those keeps the nodes on the map
+    #[serde(default)]
+    pub resource_nodes: Option<Vec<SavedResourceNode>>,
+    /// Resource stock of every team that has spent or earned anything
+    pub resources: Vec<(u8, u32)>,
+}
+
+impl SavedUnit {
+    pub fn capture(
+        executable: &Executable,
+        transform: &Transform,
+        team: &Team,
+        instance_id: &InstanceId,
+        health: &Health,
+        inventory: Option<&Inventory>,
+        max_speed: Option<&MaxSpeed>,
+    ) -> Self {
+        let snapshot = executable.snapshot();
+        let inventory = inventory.copied().unwrap_or_default();
+
+        SavedUnit {
+            unit_type_id: executable.unit_type_id,
+            version_id: executable.version_id,
+            instance_id: instance_id.0,
+            team: team.0,
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+            scale: transform.scale.to_array(),
+            hp: health.hp,
+            max_hp: health.max_hp,
+            armor: health.armor,
+            max_speed: max_speed.copied().unwrap_or_default().0,
+            carried: inventory.carried,
+            capacity: inventory.capacity,
+            installed_devices: executable.device.installed.0,
+            cycle_budget: executable.limits.num_cycles,
+            cycles_left: executable.cycles_left,
+            out_of_cycles: executable.out_of_cycles,
+            handled_hops: executable.handled_hops,
+            pc: executable.pc,
+            breakpoints: executable.breakpoints.iter().copied().collect(),
+            vector_queue: executable.vector_queue.clone(),
+            timers: executable
+                .device
+                .timer
+                .slots
+                .iter()
+                .map(|slot| {
+                    slot.map(|s| SavedTimer {
+                        vector: s.vector,
+                        fire_at: s.fire_at,
+                        period: s.period,
+                    })
+                })
+                .collect(),
+            ram: BASE64.encode(&snapshot.ram),
+            devices: BASE64.encode(&snapshot.devices),
+            stack: snapshot.stack,
+            ret: snapshot.ret,
+            rom: BASE64.encode(&executable.program.rom),
+            symbols: executable.program.symbol_table.clone(),
+            label_sizes: executable.program.label_sizes.clone(),
+            device_state: SavedDeviceState::capture(&executable.device),
+            instance_key: 0,
+            storage: Vec::new(),
+        }
+    }
+
+    /// Rebuilds the unit's executable, exactly as it was when saved
+    pub fn executable(&self) -> anyhow::Result<Executable> {
+        let snapshot = VmSnapshot {
+            ram: BASE64.decode(&self.ram)?,
+            devices: BASE64.decode(&self.devices)?,
+            stack: self.stack.clone(),
+            ret: self.ret.clone(),
+        };
+        if snapshot.ram.len() != 0x10000 || snapshot.devices.len() != 0x100 {
+            anyhow::bail!("unit {:04X} has a truncated memory dump", self.instance_id);
+        }
+
+        let program = Program {
+            rom: BASE64.decode(&self.rom)?,
+            symbol_table: self.symbols.clone(),
+            label_sizes: self.label_sizes.clone(),
+        };
+
+        let mut executable = Executable::from_snapshot(
+            self.unit_type_id,
+            &program,
+            InstalledDevices(self.installed_devices),
+            &snapshot,
+        );
+        executable.version_id = self.version_id;
+        executable.limits.num_cycles = self.cycle_budget;
+        executable.cycles_left = self.cycles_left;
+        executable.out_of_cycles = self.out_of_cycles;
+        executable.handled_hops = self.handled_hops;
+        executable.pc = self.pc;
+        executable.breakpoints = self.breakpoints.iter().copied().collect();
+        executable.vector_queue = self.vector_queue.clone();
+
+        let slots = &mut executable.device.timer.slots;
+        for (slot, timer) in slots.iter_mut().zip(&self.timers) {
+            *slot = timer.map(|t| ScheduledVector {
+                vector: t.vector,
+                fire_at: t.fire_at,
+                period: t.period,
+            });
+        }
+
+        self.device_state.restore(&mut executable.device);
+
+        Ok(executable)
+    }
+
+    /// Writes the unit's own storage back into the repository, returning the key it's under
+    pub fn restore_storage(&self, repo: &UnitRepository) -> anyhow::Result<u64> {
+        if self.instance_key == 0 {
+            return Ok(repo.new_instance_key(self.unit_type_id)?);
+        }
+
+        let blobs = self
+            .storage
+            .iter()
+            .map(|blob| Ok((blob.name.clone(), BASE64.decode(&blob.data)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        repo.claim_instance_key(self.instance_key, self.unit_type_id)?;
+        repo.replace_instance_blobs(self.unit_type_id, self.instance_key, &blobs)?;
+        Ok(self.instance_key)
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: Vec3::from_array(self.translation),
+            rotation: Quat::from_array(self.rotation),
+            scale: Vec3::from_array(self.scale),
+        }
+    }
+}
+
+impl SavedDeviceState {
+    pub fn capture(device: &UnitIO) -> Self {
+        let (out_partial, err_partial) = device.console.pending_text();
+        let order = |o: &BuildOrder| SavedBuildOrder {
+            unit_type: o.unit_type,
+            dx: o.dx,
+            dy: o.dy,
+        };
+
+        SavedDeviceState {
+            widgets: device
+                .interface
+                .widgets
+                .iter()
+                .map(|(id, widget)| SavedWidget {
+                    id: *id,
+                    kind: match widget.kind {
+                        WidgetKind::Label => 0x01,
+                        WidgetKind::Button => 0x02,
+                        WidgetKind::Toggle => 0x03,
+                    },
+                    text: widget.text.clone(),
+                    value: widget.value,
+                })
+                .collect(),
+            factory_order: device.factory.pending_order.as_ref().map(order),
+            factory_job: device.factory.job.map(|job| SavedBuildJob {
+                order: order(&job.order),
+                ticks_left: job.ticks_left,
+            }),
+            factory_refund_due: device.factory.refund_due,
+            weapon_cooldown_left: device.weapon.cooldown_left,
+            weapon_pending_shot: device.weapon.pending_shot.map(|shot| match shot {
+                ShotTarget::Handle(handle) => SavedShot::Handle(handle),
+                ShotTarget::Coords(x, y) => SavedShot::Coords(x, y),
+            }),
+            console_lines: device
+                .console
+                .lines
+                .iter()
+                .map(|line| SavedConsoleLine {
+                    text: line.text.clone(),
+                    is_error: line.is_error,
+                })
+                .collect(),
+            console_partial: (out_partial.to_string(), err_partial.to_string()),
+            sensor_pending_scan: device.sensor.pending_scan,
+            sensor_results: device
+                .sensor
+                .results
+                .iter()
+                .map(|r| SavedScanResult {
+                    dx: r.dx,
+                    dy: r.dy,
+                    kind: r.kind,
+                    unit_type: r.unit_type,
+                    friend: r.friend,
+                })
+                .collect(),
+            radio_inbox: device
+                .radio
+                .inbox
+                .iter()
+                .map(|p| SavedPacket {
+                    payload: p.payload.clone(),
+                    frequency: p.frequency,
+                    sender: p.sender,
+                    rssi: p.rssi,
+                    hops: p.hops,
+                })
+                .collect(),
+            radio_extra_frequencies: device.radio.extra_frequencies.iter().copied().collect(),
+            radio_scanning: device.radio.scanning,
+            random_state: device.random.state(),
+            spawn_tick: device.identity.spawn_tick,
+        }
+    }
+
+    pub fn restore(&self, device: &mut UnitIO) {
+        let order = |o: &SavedBuildOrder| BuildOrder {
+            unit_type: o.unit_type,
+            dx: o.dx,
+            dy: o.dy,
+        };
+
+        device.interface.widgets = self
+            .widgets
+            .iter()
+            .filter_map(|w| {
+                let kind = match w.kind {
+                    0x01 => WidgetKind::Label,
+                    0x02 => WidgetKind::Button,
+                    0x03 => WidgetKind::Toggle,
+                    _ => return None,
+                };
+                let widget = Widget {
+                    kind,
+                    text: w.text.clone(),
+                    value: w.value,
+                };
+                Some((w.id, widget))
+            })
+            .collect();
+
+        device.factory.pending_order = self.factory_order.as_ref().map(order);
+        device.factory.job = self.factory_job.map(|job| BuildJob {
+            order: order(&job.order),
+            ticks_left: job.ticks_left,
+        });
+        device.factory.refund_due = self.factory_refund_due;
+
+        device.weapon.cooldown_left = self.weapon_cooldown_left;
+        device.weapon.pending_shot = self.weapon_pending_shot.map(|shot| match shot {
+            SavedShot::Handle(handle) => ShotTarget::Handle(handle),
+            SavedShot::Coords(x, y) => ShotTarget::Coords(x, y),
+        });
+
+        device.console.clear();
+        device.console.lines = self
+            .console_lines
+            .iter()
+            .map(|line| ConsoleLine {
+                text: line.text.clone(),
+                is_error: line.is_error,
+            })
+            .collect();
+        // Partial lines never hold a newline, so this can't complete a line
+        for b in self.console_partial.0.bytes() {
+            device.console.push_byte(b, false);
+        }
+        for b in self.console_partial.1.bytes() {
+            device.console.push_byte(b, true);
+        }
+
+        device.sensor.pending_scan = self.sensor_pending_scan;
+        device.random.set_state(self.random_state);
+        device.identity.spawn_tick = self.spawn_tick;
+        device.sensor.results = self
+            .sensor_results
+            .iter()
+            .map(|r| ScanResult {
+                dx: r.dx,
+                dy: r.dy,
+                kind: r.kind,
+                unit_type: r.unit_type,
+                friend: r.friend,
+            })
+            .collect();
+
+        device.radio.inbox = self
+            .radio_inbox
+            .iter()
+            .map(|p| ReceivedPacket {
+                payload: p.payload.clone(),
+                frequency: p.frequency,
+                sender: p.sender,
+                rssi: p.rssi,
+                hops: p.hops,
+            })
+            .collect();
+        device.radio.extra_frequencies = self.radio_extra_frequencies.iter().copied().collect();
+        device.radio.scanning = self.radio_scanning;
+    }
+}
+
+pub fn write_save(save: &WorldSave, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path)?;
+    serde_json::to_writer(std::io::BufWriter::new(file), save)?;
+    Ok(())
+}
+
+pub fn read_save(path: impl AsRef<Path>) -> anyhow::Result<WorldSave> {
+    let file = std::fs::File::open(path)?;
+    let save: WorldSave = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+    if save.format_version > SAVE_FORMAT_VERSION {
+        anyhow::bail!(
+            "save format {} is newer than the supported {}",
+            save.format_version,
+            SAVE_FORMAT_VERSION
+        );
+    }
+
+    Ok(save)
+}
+
+#[derive(Event)]
+pub struct SaveWorldRequest {
+    pub path: String,
+}
+
+#[derive(Event)]
+pub struct LoadWorldRequest {
+    pub path: String,
+}
+
+#[derive(Resource)]
+struct WorldSaveState {
+    path: String,
+    /// Outcome of the last save or load
+    status: String,
+}
+
+impl Default for WorldSaveState {
+    fn default() -> Self {
+        WorldSaveState {
+            path: DEFAULT_SAVE_PATH.to_string(),
+            status: String::new(),
+        }
+    }
+}
+
+fn save_world(
+    mut requests: EventReader<SaveWorldRequest>,
+    units: Query<
+        (
+            &Executable,
+            &Transform,
+            &Team,
+            &InstanceId,
+            &InstanceKey,
+            &Health,
+            Option<&Inventory>,
+            Option<&MaxSpeed>,
+        ),
+        With<Unit>,
+    >,
+    depots: Query<(&Transform, &Team), With<Depot>>,
+    nodes: Query<(&Transform, &ResourceNode, &Sprite)>,
+    repo: Res<UnitRepository>,
+    tick: Res<SimulationTick>,
+    next_instance_id: Res<NextInstanceId>,
+    resources: Res<TeamResources>,
+    mut state: ResMut<WorldSaveState>,
+) {
+    for request in requests.read() {
+        let saved_units = units
+            .iter()
+            .map(|(executable, transform, team, instance_id, instance_key, health, inventory, max_speed)| {
+                let storage = repo.instance_blobs(executable.unit_type_id, instance_key.0)?;
+                Ok(SavedUnit {
+                    instance_key: instance_key.0,
+                    storage: storage
+                        .into_iter()
+                        .map(|(name, data)| SavedBlob {
+                            name,
+                            data: BASE64.encode(data),
+                        })
+                        .collect(),
+                    ..SavedUnit::capture(
+                        executable,
+                        transform,
+                        team,
+                        instance_id,
+                        health,
+                        inventory,
+                        max_speed,
+                    )
+                })
+            })
+            .collect::<RepoResult<Vec<_>>>();
+        let saved_units = match saved_units {
+            Ok(saved_units) => saved_units,
+            Err(e) => {
+                state.status = format!("Save failed: {}", e);
+                continue;
+            }
+        };
+
+        let save = WorldSave {
+            format_version: SAVE_FORMAT_VERSION,
+            tick: tick.0,
+            next_instance_id: next_instance_id.0,
+            units: saved_units,
+            depots: depots
+                .iter()
+                .map(|(transform, team)| SavedDepot {
+                    team: team.0,
+                    position: transform.translation.xy().to_array(),
+                })
+                .collect(),
+            resource_nodes: Some(
+                nodes
+                    .iter()
+                    .map(|(transform, node, sprite)| SavedResourceNode {
+                        translation: transform.translation.to_array(),
+                        scale: transform.scale.to_array(),
+                        amount: node.amount,
+                        sprite: sprite.texture_atlas.as_ref().map_or(0, |atlas| atlas.index),
+                    })
+                    .collect(),
+            ),
+            resources: resources.teams(),
+        };
+
+        state.status = match write_save(&save, &request.path) {
+            Ok(()) => format!("Saved {} units to {}", save.units.len(), request.path),
+            Err(e) => format!("Save failed: {}", e),
+        };
+    }
+}
+
+fn load_world(
+    mut requests: EventReader<LoadWorldRequest>,
+    mut commands: Commands,
+    existing: Query<Entity, Or<(With<Unit>, With<Depot>)>>,
+    existing_nodes: Query<Entity, With<ResourceNode>>,
+    repo: Res<UnitRepository>,
+    asset_lib: Res<AssetLibrary>,
+    mut tick: ResMut<SimulationTick>,
+    mut next_instance_id: ResMut<NextInstanceId>,
+    mut resources: ResMut<TeamResources>,
+    mut depot_requests: EventWriter<SpawnDepotRequest>,
+    mut state: ResMut<WorldSaveState>,
+) {
+    let Some(request) = requests.read().last() else {
+        return;
+    };
+
+    let loaded = read_save(&request.path).and_then(|save| {
+        let executables = save
+            .units
+            .iter()
+            .map(|unit| unit.executable())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let instance_keys = save
+            .units
+            .iter()
+            .map(|unit| unit.restore_storage(&repo))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok((save, executables, instance_keys))
+    });
+    let (save, executables, instance_keys) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            state.status = format!("Load failed: {}", e);
+            return;
+        }
+    };
+
+    for entity in &existing {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    if let Some(nodes) = &save.resource_nodes {
+        for entity in &existing_nodes {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        let asset = &asset_lib.assets["roguelike"];
+        for node in nodes {
+            commands.spawn((
+                Sprite::from_atlas_image(
+                    asset.image.clone(),
+                    TextureAtlas {
+                        layout: asset.layout.clone(),
+                        index: node.sprite,
+                    },
+                ),
+                Transform {
+                    translation: Vec3::from_array(node.translation),
+                    scale: Vec3::from_array(node.scale),
+                    ..default()
+                },
+                ResourceNode {
+                    amount: node.amount,
+                },
+            ));
+        }
+    }
+
+    for ((unit, executable), instance_key) in save.units.iter().zip(executables).zip(instance_keys) {
+        // Looks come from the unit type, if it's still around
+        let sprite = repo
+            .get_unit(unit.unit_type_id)
+            .map(|u| u.stats.sprite)
+            .unwrap_or_default();
+        let stats = UnitStats {
+            sprite,
+            scale: unit.scale[0],
+            max_speed: unit.max_speed,
+            max_hp: unit.max_hp,
+            armor: unit.armor,
+            cycle_budget: unit.cycle_budget,
+            devices: InstalledDevices(unit.installed_devices),
+        };
+        let transform = unit.transform();
+
+        commands
+            .spawn(UnitBundle::with_executable(
+                executable,
+                unit_sprite(&asset_lib, &stats.sprite),
+                &stats,
+                UnitSpawn {
+                    instance_id: InstanceId(unit.instance_id),
+                    instance_key: InstanceKey(instance_key),
+                    team: unit.team,
+                    pos: transform.translation.xy(),
+                    spawn_tick: unit.device_state.spawn_tick,
+                    version_id: unit.version_id,
+                },
+            ))
+            .insert((
+                transform,
+                Health {
+                    hp: unit.hp,
+                    max_hp: unit.max_hp,
+                    armor: unit.armor,
+                },
+                Inventory {
+                    carried: unit.carried,
+                    capacity: unit.capacity,
+                },
+            ));
+    }
+
+    depot_requests.send_batch(save.depots.iter().map(|depot| SpawnDepotRequest {
+        team: depot.team,
+        position: Vec2::from_array(depot.position),
+    }));
+
+    *resources = TeamResources::default();
+    for (team, stock) in &save.resources {
+        resources.set(*team, *stock);
+    }
+    tick.0 = save.tick;
+    next_instance_id.0 = save.next_instance_id;
+
+    state.status = format!("Loaded {} units from {}", save.units.len(), request.path);
+}
+
+fn draw_world_window(
+    mut context: EguiContexts,
+    mut state: ResMut<WorldSaveState>,
+    mut save_requests: EventWriter<SaveWorldRequest>,
+    mut load_requests: EventWriter<LoadWorldRequest>,
+) {
+    egui::Window::new("World".to_string())
+        .default_open(false)
+        .show(context.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.text_edit_singleline(&mut state.path);
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    save_requests.send(SaveWorldRequest {
+                        path: state.path.clone(),
+                    });
+                }
+                if ui
+                    .button("Load")
+                    .on_hover_text("Replaces every unit and depot on the map")
+                    .clicked()
+                {
+                    load_requests.send(LoadWorldRequest {
+                        path: state.path.clone(),
+                    });
+                }
+            });
+            if !state.status.is_empty() {
+                ui.label(&state.status);
+            }
+        });
+}
+
+pub struct WorldSavePlugin;
+
+impl Plugin for WorldSavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveWorldRequest>()
+            .add_event::<LoadWorldRequest>()
+            .init_resource::<WorldSaveState>()
+            .add_systems(PreUpdate, (save_world, load_world).chain())
+            .add_systems(Update, draw_world_window);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::assembler::assemble;
+
+    #[test]
+    fn test_saved_unit_round_trip() {
+        let program = assemble("|100 #1234 #56 STH BRK".to_string()).unwrap();
+        let mut executable = Executable::from_program(7, &program);
+        executable.cpu.ram_write_byte(0x0010, 0xAB);
+        executable.pc = Some(0x0104);
+        executable.breakpoints.insert(0x0102);
+        executable.vector_queue.push(0x0200);
+        executable.device.timer.slots[1] = Some(ScheduledVector {
+            vector: 0x0300,
+            fire_at: 42,
+            period: 5,
+        });
+
+        let device = &mut executable.device;
+        device.interface.widgets.insert(
+            4,
+            Widget {
+                kind: WidgetKind::Toggle,
+                text: "Patrol".to_string(),
+                value: 1,
+            },
+        );
+        let order = BuildOrder {
+            unit_type: 2,
+            dx: -16,
+            dy: 8,
+        };
+        device.factory.pending_order = Some(order);
+        device.factory.job = Some(BuildJob {
+            order,
+            ticks_left: 30,
+        });
+        device.weapon.cooldown_left = 3;
+        device.weapon.pending_shot = Some(ShotTarget::Coords(5, 6));
+        for b in "done\nhal".bytes() {
+            device.console.push_byte(b, false);
+        }
+        device.sensor.results.push(ScanResult {
+            dx: -3,
+            dy: 4,
+            kind: 1,
+            unit_type: 2,
+            friend: true,
+        });
+        device.radio.inbox.push_back(ReceivedPacket {
+            payload: vec![1, 2, 3],
+            frequency: 7,
+            sender: 9,
+            rssi: 200,
+            hops: 1,
+        });
+        device.radio.extra_frequencies.insert(12);
+        device.radio.scanning = true;
+        device.random.reseed(5, 9);
+        device.random.next_u64();
+        device.identity.spawn_tick = 1234;
+
+        let transform = Transform::from_xyz(10., -20., 0.).with_scale(Vec3::new(2., 2., 1.));
+        let saved = SavedUnit::capture(
+            &executable,
+            &transform,
+            &Team(3),
+            &InstanceId(9),
+            &Health::new(50, 1),
+            None,
+            Some(&MaxSpeed(4.0)),
+        );
+        let json = serde_json::to_string(&saved).unwrap();
+        let saved: SavedUnit = serde_json::from_str(&json).unwrap();
+
+        let restored = saved.executable().unwrap();
+        assert_eq!(restored.snapshot(), executable.snapshot());
+        assert_eq!(restored.snapshot().stack, vec![0x12, 0x34]);
+        assert_eq!(restored.snapshot().ret, vec![0x56]);
+        assert_eq!(restored.pc, Some(0x0104));
+        assert!(restored.has_breakpoint_at(&0x0102));
+        assert_eq!(restored.vector_queue, vec![0x0200]);
+        assert_eq!(restored.device.timer.slots, executable.device.timer.slots);
+        assert_eq!(restored.unit_type_id, 7);
+
+        let (before, after) = (&executable.device, &restored.device);
+        assert_eq!(after.interface.widgets, before.interface.widgets);
+        assert_eq!(after.factory.pending_order, Some(order));
+        assert_eq!(after.factory.job, before.factory.job);
+        assert_eq!(after.weapon.cooldown_left, 3);
+        assert_eq!(after.weapon.pending_shot, before.weapon.pending_shot);
+        assert_eq!(after.console.lines, before.console.lines);
+        assert_eq!(after.console.pending_text(), ("hal", ""));
+        assert_eq!(after.sensor.results, before.sensor.results);
+        assert_eq!(after.radio.inbox, before.radio.inbox);
+        assert_eq!(after.radio.extra_frequencies, before.radio.extra_frequencies);
+        assert!(after.radio.scanning);
+        assert_eq!(after.random.state(), before.random.state());
+        assert_eq!(after.identity.spawn_tick, 1234);
+        assert_eq!(saved.transform(), transform);
+    }
+
+    #[test]
+    fn test_unit_storage_travels_with_the_save() {
+        let repo = crate::unit_repo::tests::test_repository();
+        let unit_type_id = repo.new_unit_type("scout".to_string()).unwrap();
+        let program = assemble("|100 BRK".to_string()).unwrap();
+        let executable = Executable::from_program(unit_type_id, &program);
+
+        let saved = SavedUnit {
+            unit_type_id,
+            instance_key: 40,
+            storage: vec![SavedBlob {
+                name: "home".to_string(),
+                data: BASE64.encode([1, 2]),
+            }],
+            ..SavedUnit::capture(
+                &executable,
+                &Transform::default(),
+                &Team(1),
+                &InstanceId(1),
+                &Health::new(10, 0),
+                None,
+                None,
+            )
+        };
+        repo.write_blob(unit_type_id, 40, "stale", &[9], false).unwrap();
+
+        assert_eq!(saved.restore_storage(&repo).unwrap(), 40);
+        assert_eq!(
+            repo.instance_blobs(unit_type_id, 40).unwrap(),
+            vec![("home".to_string(), vec![1, 2])]
+        );
+        assert!(repo.new_instance_key(unit_type_id).unwrap() > 40);
+    }
+}